@@ -0,0 +1,44 @@
+//! CPU cost of the resampler presets for a single bridge.
+//!
+//! One iteration processes one second of audio in both directions, the same
+//! work a bridge does with one Discord talker and one RF talker keyed at once:
+//! 50 chunks of 960 samples 48 kHz -> 8 kHz and 50 chunks of 160 samples 8 kHz -> 48 kHz.
+//!
+//! Run with `cargo bench --bench resample`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use discord_bridge::resample::{MonoResampler, ResamplerQuality};
+
+fn tone(len: usize, rate: f64) -> Vec<f64> {
+    (0..len)
+        .map(|i| 0.5 * (2.0 * std::f64::consts::PI * 1000.0 * i as f64 / rate).sin())
+        .collect()
+}
+
+fn bridge_second(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bridge_second");
+    let discord_chunk = tone(960, 48000.0);
+    let rf_chunk = tone(160, 8000.0);
+
+    for quality in [
+        ResamplerQuality::Fast,
+        ResamplerQuality::Balanced,
+        ResamplerQuality::High,
+    ] {
+        let mut to_rf = MonoResampler::new(quality, 48000, 8000, 960).unwrap();
+        let mut to_discord = MonoResampler::new(quality, 8000, 48000, 160).unwrap();
+
+        group.bench_function(quality.to_string(), |b| {
+            b.iter(|| {
+                for _ in 0..50 {
+                    black_box(to_rf.process(discord_chunk.clone()));
+                    black_box(to_discord.process(rf_chunk.clone()));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bridge_second);
+criterion_main!(benches);
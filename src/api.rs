@@ -0,0 +1,273 @@
+use axum::{
+    extract::{Path, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use log::{info, warn};
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use serenity::all::{ChannelId, ConnectionStage, Context, GuildId, ShardManager};
+use std::{
+    net::SocketAddr,
+    num::NonZeroU64,
+    path::{Component, Path as FilePath, PathBuf},
+    sync::Arc,
+};
+
+use crate::{session, usrp::SAMPLE_RATES, Data};
+
+#[derive(Clone)]
+pub struct ApiState {
+    pub ctx: Context,
+    pub data: Data,
+    pub shard_manager: Arc<ShardManager>,
+}
+
+/// Serve the admin API until the process exits
+pub async fn serve(addr: SocketAddr, state: ApiState) {
+    let app = Router::new()
+        .route("/health", get(health))
+        .route("/bridges", get(bridges))
+        .route("/bridges/{guild_id}/join", post(join))
+        .route("/bridges/{guild_id}/leave", post(leave))
+        .route("/bridges/{guild_id}/announce", post(announce))
+        .route_layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state);
+    let listener = match tokio::net::TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Failed to bind admin API {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("Serving admin API on http://{}", addr);
+    if let Err(e) = axum::serve(listener, app).await {
+        warn!("Admin API failed: {}", e);
+    }
+}
+
+fn error(status: StatusCode, message: impl ToString) -> Response {
+    (status, Json(json!({ "error": message.to_string() }))).into_response()
+}
+
+/// A Discord id from a request, None for 0 which the id types panic on
+fn id<T: From<NonZeroU64>>(id: u64) -> Option<T> {
+    NonZeroU64::new(id).map(T::from)
+}
+
+/// Compare without returning early, so the time taken does not tell how much of the token matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Check the bearer token, requests are refused when `API_TOKEN` is missing
+async fn authorize(State(state): State<ApiState>, request: Request, next: Next) -> Response {
+    let Some(token) = &state.data.config.api_token else {
+        return error(StatusCode::UNAUTHORIZED, "Invalid or missing token");
+    };
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "))
+        .is_some_and(|x| constant_time_eq(x.as_bytes(), token.as_bytes()));
+    if !authorized {
+        return error(StatusCode::UNAUTHORIZED, "Invalid or missing token");
+    }
+    next.run(request).await
+}
+
+#[derive(Serialize)]
+struct BridgeHealth {
+    guild_id: u64,
+    /// Seconds since the last USRP packet, or since the bridge was created
+    rx_idle_secs: u64,
+    healthy: bool,
+}
+
+/// 200 when the gateway is connected and there are bridges that all saw USRP traffic recently,
+/// 503 otherwise
+async fn health(State(state): State<ApiState>) -> Response {
+    let gateway_connected = state
+        .shard_manager
+        .runners
+        .lock()
+        .await
+        .values()
+        .any(|x| x.stage == ConnectionStage::Connected);
+
+    let timeout = state.data.config.health_rx_timeout;
+    let bridges: Vec<_> = state
+        .data
+        .bridges
+        .lock()
+        .await
+        .iter()
+        .map(|(&guild_id, bridge)| {
+            let stats = bridge.client.stats();
+            let rx_idle = stats.last_rx().map_or(stats.started, |x| x.at).elapsed();
+            BridgeHealth {
+                guild_id,
+                rx_idle_secs: rx_idle.as_secs(),
+                healthy: timeout.is_zero() || rx_idle < timeout,
+            }
+        })
+        .collect();
+
+    // No bridge at all, e.g. when every AUTO_JOIN failed, is not healthy either
+    let healthy = gateway_connected && !bridges.is_empty() && bridges.iter().all(|x| x.healthy);
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "healthy": healthy,
+        "gateway_connected": gateway_connected,
+        "bridges": bridges,
+    });
+    (status, Json(body)).into_response()
+}
+
+#[derive(Serialize)]
+struct BridgeInfo {
+    guild_id: u64,
+    channel_id: Option<u64>,
+    /// `USRP` or `Mumble`
+    backend: String,
+    peer: String,
+    talker: Option<String>,
+    packets_in: u64,
+    packets_out: u64,
+    last_rx_secs: Option<u64>,
+    discord_users: Vec<String>,
+    uptime_secs: u64,
+}
+
+async fn bridges(State(state): State<ApiState>) -> Response {
+    let clients: Vec<_> = state
+        .data
+        .bridges
+        .lock()
+        .await
+        .iter()
+        .map(|(&guild_id, bridge)| (guild_id, bridge.client.clone()))
+        .collect();
+    let manager = songbird::get(&state.ctx).await;
+
+    let mut bridges = Vec::new();
+    for (guild_id, client) in clients {
+        let call = manager.as_ref().and_then(|x| x.get(GuildId::new(guild_id)));
+        let channel_id = match call {
+            Some(call) => call.lock().await.current_channel().map(|x| x.0.get()),
+            None => None,
+        };
+        let stats = client.stats();
+        bridges.push(BridgeInfo {
+            guild_id,
+            channel_id,
+            backend: state.data.config.backend.to_string(),
+            peer: client.peer(),
+            talker: stats.talker(),
+            packets_in: stats.packets_in(),
+            packets_out: stats.packets_out(),
+            last_rx_secs: stats.last_rx().map(|x| x.at.elapsed().as_secs()),
+            discord_users: stats.users().into_iter().map(|(_, user)| user).collect(),
+            uptime_secs: stats.started.elapsed().as_secs(),
+        });
+    }
+    bridges.sort_by_key(|x| x.guild_id);
+    Json(bridges).into_response()
+}
+
+#[derive(Deserialize)]
+struct JoinRequest {
+    channel_id: u64,
+    /// USRP sample rate of this guild, kept for later joins
+    sample_rate: Option<usize>,
+}
+
+async fn join(
+    State(state): State<ApiState>,
+    Path(guild_id): Path<u64>,
+    Json(request): Json<JoinRequest>,
+) -> Response {
+    let Some(guild_id): Option<GuildId> = id(guild_id) else {
+        return error(StatusCode::BAD_REQUEST, "guild_id must not be 0");
+    };
+    let Some(channel_id): Option<ChannelId> = id(request.channel_id) else {
+        return error(StatusCode::BAD_REQUEST, "channel_id must not be 0");
+    };
+    info!("Admin API: joining {} in guild {}", channel_id, guild_id);
+    if let Some(sample_rate) = request.sample_rate {
+        if !SAMPLE_RATES.contains(&sample_rate) {
+            return error(StatusCode::BAD_REQUEST, "sample_rate must be 8000 or 16000");
+        }
+        let settings = state.data.guild_settings(guild_id).await;
+        settings.set_usrp_sample_rate(sample_rate);
+    }
+    match session::join(&state.ctx, &state.data, guild_id, channel_id).await {
+        Ok(()) => Json(json!({ "channel_id": channel_id.get() })).into_response(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+async fn leave(State(state): State<ApiState>, Path(guild_id): Path<u64>) -> Response {
+    let Some(guild_id): Option<GuildId> = id(guild_id) else {
+        return error(StatusCode::BAD_REQUEST, "guild_id must not be 0");
+    };
+    info!("Admin API: leaving guild {}", guild_id);
+    match session::leave(&state.ctx, &state.data, guild_id).await {
+        Ok(Some(channel_id)) => Json(json!({ "channel_id": channel_id.get() })).into_response(),
+        Ok(None) => error(StatusCode::NOT_FOUND, "Not in a voice channel"),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+#[derive(Deserialize)]
+struct AnnounceRequest {
+    /// Audio file to play in the voice channel, relative to `ANNOUNCE_DIR`
+    file: PathBuf,
+}
+
+/// Path of an announcement inside `dir`, None for absolute paths, `..`, links
+/// out of the directory and files that do not exist
+fn resolve_announcement(dir: &FilePath, file: &FilePath) -> Option<PathBuf> {
+    if file.as_os_str().is_empty() || !file.components().all(|x| matches!(x, Component::Normal(_)))
+    {
+        return None;
+    }
+    let dir = dir.canonicalize().ok()?;
+    let path = dir.join(file).canonicalize().ok()?;
+    (path.starts_with(&dir) && path.is_file()).then_some(path)
+}
+
+async fn announce(
+    State(state): State<ApiState>,
+    Path(guild_id): Path<u64>,
+    Json(request): Json<AnnounceRequest>,
+) -> Response {
+    let Some(guild_id): Option<GuildId> = id(guild_id) else {
+        return error(StatusCode::BAD_REQUEST, "guild_id must not be 0");
+    };
+    let Some(dir) = &state.data.config.announce_dir else {
+        return error(StatusCode::NOT_FOUND, "Announcements are disabled");
+    };
+    // The same answer for every refused path, so it does not tell which files exist
+    let Some(path) = resolve_announcement(dir, &request.file) else {
+        return error(StatusCode::BAD_REQUEST, "Invalid file");
+    };
+    info!(
+        "Admin API: announcing {} in guild {}",
+        path.display(),
+        guild_id
+    );
+    match session::announce(&state.ctx, guild_id, path).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => error(StatusCode::NOT_FOUND, e),
+    }
+}
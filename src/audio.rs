@@ -0,0 +1,188 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    f32::consts::FRAC_PI_4,
+    fmt,
+    hash::Hash,
+    str::FromStr,
+};
+
+/// How the stereo audio received from Discord is folded into the mono RF channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ChannelMode {
+    Left,
+    Right,
+    /// Average of both channels
+    #[default]
+    Sum,
+    /// Whichever channel is louder at each sample
+    Max,
+}
+
+impl FromStr for ChannelMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "left" | "l" => Ok(Self::Left),
+            "right" | "r" => Ok(Self::Right),
+            "sum" | "mix" => Ok(Self::Sum),
+            "max" => Ok(Self::Max),
+            _ => Err(format!("Unknown channel mode: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ChannelMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Left => write!(f, "left"),
+            Self::Right => write!(f, "right"),
+            Self::Sum => write!(f, "sum"),
+            Self::Max => write!(f, "max"),
+        }
+    }
+}
+
+/// Convert interleaved L, R, L, R samples to mono in the range [-1, 1]
+pub fn downmix(interleaved: &[i16], mode: ChannelMode) -> Vec<f64> {
+    interleaved
+        .chunks_exact(2)
+        .map(|x| {
+            let (l, r) = (x[0] as f64, x[1] as f64);
+            match mode {
+                ChannelMode::Left => l / 32768.0,
+                ChannelMode::Right => r / 32768.0,
+                ChannelMode::Sum => (l + r) / 65536.0,
+                ChannelMode::Max => {
+                    if l.abs() >= r.abs() {
+                        l / 32768.0
+                    } else {
+                        r / 32768.0
+                    }
+                }
+            }
+        })
+        .collect()
+}
+
+/// Pan positions handed out to new sources, from -1 (left) to 1 (right)
+const PAN_POSITIONS: [f32; 5] = [0.0, -0.6, 0.6, -0.3, 0.3];
+
+/// Places every audio source at its own position in the stereo field
+pub struct Panner<K> {
+    positions: HashMap<K, f32>,
+}
+
+impl<K: Eq + Hash> Panner<K> {
+    pub fn new() -> Self {
+        Self {
+            positions: HashMap::new(),
+        }
+    }
+
+    /// Position of a source, allocating the first free slot for unknown sources
+    pub fn position(&mut self, source: K) -> f32 {
+        let taken: Vec<f32> = self.positions.values().copied().collect();
+        *self.positions.entry(source).or_insert_with(|| {
+            PAN_POSITIONS
+                .into_iter()
+                .find(|x| !taken.contains(x))
+                .unwrap_or(PAN_POSITIONS[taken.len() % PAN_POSITIONS.len()])
+        })
+    }
+
+    /// Forget a source, freeing its slot
+    pub fn remove(&mut self, source: &K) {
+        self.positions.remove(source);
+    }
+
+    /// Constant power (left, right) gains for a source
+    pub fn gains(&mut self, source: K) -> (f32, f32) {
+        let angle = (self.position(source) + 1.0) * FRAC_PI_4;
+        (angle.cos(), angle.sin())
+    }
+}
+
+impl<K: Eq + Hash> Default for Panner<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Audio queued for one source of a `Mixer`
+#[derive(Default)]
+struct MixerInput {
+    buffer: VecDeque<f32>,
+    /// Set once two frames are queued, the spare frame absorbs packets
+    /// arriving a little late or short. Cleared when the source runs dry
+    playing: bool,
+    /// Play out what is queued, then drop the source
+    ending: bool,
+}
+
+/// Adds up the audio of several sources into a single stream, one frame at a time
+pub struct Mixer<K> {
+    inputs: HashMap<K, MixerInput>,
+    /// Samples kept per source before the oldest are dropped
+    max_buffered: usize,
+}
+
+impl<K: Eq + Hash> Mixer<K> {
+    pub fn new(max_buffered: usize) -> Self {
+        Self {
+            inputs: HashMap::new(),
+            max_buffered,
+        }
+    }
+
+    /// Queue samples of a source
+    pub fn push(&mut self, source: K, samples: impl IntoIterator<Item = f32>) {
+        let input = self.inputs.entry(source).or_default();
+        input.buffer.extend(samples);
+        input.ending = false;
+        if input.buffer.len() > self.max_buffered {
+            let excess = input.buffer.len() - self.max_buffered;
+            input.buffer.drain(..excess);
+        }
+    }
+
+    /// Play out the audio a source still has queued, then drop it
+    pub fn end(&mut self, source: &K) {
+        if let Some(input) = self.inputs.get_mut(source) {
+            input.ending = true;
+        }
+    }
+
+    /// Number of sources with audio queued or playing
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Take `len` samples of every playing source and add them up, clamped
+    /// to [-1, 1]. None when no source has audio to play
+    pub fn mix(&mut self, len: usize) -> Option<Vec<f32>> {
+        let mut frame = vec![0.0f32; len];
+        let mut mixed = false;
+        self.inputs.retain(|_, input| {
+            if input.buffer.len() >= 2 * len || input.ending {
+                input.playing = true;
+            }
+            if input.buffer.is_empty() {
+                input.playing = false;
+            }
+            if input.playing {
+                let available = input.buffer.len().min(len);
+                for (x, sample) in frame.iter_mut().zip(input.buffer.drain(..available)) {
+                    *x += sample;
+                }
+                mixed = true;
+            }
+            !(input.ending && input.buffer.is_empty())
+        });
+        mixed.then(|| frame.into_iter().map(|x| x.clamp(-1.0, 1.0)).collect())
+    }
+}
@@ -0,0 +1,122 @@
+use serenity::async_trait;
+use std::{fmt, io::Error, net::SocketAddr, str::FromStr, sync::Arc};
+
+#[cfg(feature = "mumble")]
+use crate::mumble::MumbleClient;
+use crate::{
+    config::BridgeConfig,
+    stats::BridgeStats,
+    usrp::{USRPClient, FRAME_MS},
+};
+
+/// Who is talking, announced at the start of a transmission
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Talker {
+    /// Empty when the talker did not identify
+    pub callsign: String,
+    pub dmr_id: Option<u32>,
+    pub talkgroup: Option<u32>,
+    /// Shown when there is no callsign, e.g. a Mumble user name
+    pub name: Option<String>,
+}
+
+/// What crosses the bridge: transmission edges and the audio in between
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VoicePacket {
+    Start(Option<Talker>),
+    /// One frame of `frame_size` samples at the backend sample rate
+    Audio(Vec<i16>),
+    End,
+}
+
+/// The far side of a bridge. The Discord side only talks to this trait, so a
+/// new backend needs no change to the bridge itself
+#[async_trait]
+pub trait VoiceBackend: Send + Sync {
+    /// Where the audio goes, for the status
+    fn peer(&self) -> String;
+
+    /// Sample rate of the audio frames in both directions
+    fn sample_rate(&self) -> usize;
+
+    /// Number of samples in one audio frame
+    fn frame_size(&self) -> usize {
+        self.sample_rate() * FRAME_MS / 1000
+    }
+
+    fn stats(&self) -> &Arc<BridgeStats>;
+
+    /// Receive the next packet along with the address of the peer that sent
+    /// it, None once disconnected
+    async fn recv(&self) -> Option<(VoicePacket, SocketAddr)>;
+
+    async fn send(&self, packet: VoicePacket) -> Result<(), Error>;
+
+    /// Close the connection, a pending `recv` returns None
+    fn disconnect(&self);
+}
+
+/// The kind of backend bridges are created with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    #[default]
+    Usrp,
+    #[cfg(feature = "mumble")]
+    Mumble,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "usrp" => Ok(Self::Usrp),
+            #[cfg(feature = "mumble")]
+            "mumble" => Ok(Self::Mumble),
+            #[cfg(not(feature = "mumble"))]
+            "mumble" => Err("mumble needs the mumble feature".to_string()),
+            _ => Err(format!("unknown backend {}, expected usrp or mumble", s)),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Usrp => write!(f, "USRP"),
+            #[cfg(feature = "mumble")]
+            Self::Mumble => write!(f, "Mumble"),
+        }
+    }
+}
+
+/// Connect the backend of a new bridge, USRP peers at `usrp_sample_rate`
+pub async fn connect(
+    config: &BridgeConfig,
+    usrp_sample_rate: usize,
+) -> Result<Arc<dyn VoiceBackend>, Error> {
+    match config.backend {
+        Backend::Usrp => {
+            let mut client = USRPClient::new(
+                config.local_rx_addr,
+                config.target_rx_addr,
+                None,
+                usrp_sample_rate,
+            );
+            client.connect().await?;
+            Ok(Arc::new(client))
+        }
+        #[cfg(feature = "mumble")]
+        Backend::Mumble => {
+            let mut client = MumbleClient::new(
+                config.mumble_server.clone(),
+                config.mumble_username.clone(),
+                config.mumble_password.clone(),
+                config.mumble_channel.clone(),
+                config.mumble_accept_invalid_certs,
+            );
+            client.connect().await?;
+            Ok(Arc::new(client))
+        }
+    }
+}
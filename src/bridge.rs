@@ -1,117 +1,292 @@
-use log::info;
-use rand::seq::IndexedRandom;
-use rubato::{
-    Resampler, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction,
+use chrono::Utc;
+use discord_bridge::{
+    audio::{downmix, ChannelMode},
+    callsign::{extract_callsign, Callsign},
+    lastheard::{Direction, LastHeard, Transmission},
+    radioid::RadioIdDatabase,
+    registry::CallsignRegistry,
+    resample::MonoResampler,
 };
+use log::{info, warn};
+use rand::seq::IndexedRandom;
 use serenity::{
-    all::Http,
+    all::{CreateMessage, Http, Member},
     async_trait,
     cache::Cache,
-    model::id::{GuildId, UserId},
+    model::id::{GuildId, RoleId, UserId},
 };
 use songbird::{
     events::context_data::VoiceTick,
     model::payload::{ClientDisconnect, Speaking},
     Event, EventContext, EventHandler as VoiceEventHandler,
 };
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
 use tokio::sync::Mutex;
 
+#[cfg(feature = "listen")]
+use crate::listen::ListenMixer;
 use crate::{
-    usrp::{
-        packets::{AudioPacket, EndPacket, StartPacket, USRPPacket},
-        USRPClient,
-    },
-    util::extract_callsign,
+    backend::{Talker, VoiceBackend, VoicePacket},
+    settings::GuildSettings,
+    status::StatusUpdater,
+    Data,
 };
 
+/// How often the member behind a speaking user is fetched again, so role and
+/// nickname changes apply without reconnecting
+const MEMBER_REFRESH: Duration = Duration::from_secs(30);
+
 struct UserData {
     callsign: String,
-    nick: String,
     name: String,
     id: UserId,
+    /// Licensed to key the RF side
+    can_transmit: bool,
 }
-pub struct USRPEventHandlerData {
-    client: Arc<USRPClient>,
+pub struct BridgeEventHandlerData {
+    client: Arc<dyn VoiceBackend>,
     http: Arc<Http>,
     cache: Arc<Cache>,
 
-    resampler: SincFixedIn<f64>,
+    resampler: MonoResampler,
+    /// Where the audio sent to RF is tapped for the listen stream
+    #[cfg(feature = "listen")]
+    listen: Arc<ListenMixer>,
+    channel_mode: ChannelMode,
+    require_license: bool,
+    licensed_role: Option<RoleId>,
+    require_control_op: bool,
 
     guild_id: GuildId,
+    settings: Arc<GuildSettings>,
+    radioid: Arc<RwLock<RadioIdDatabase>>,
+    registry: Arc<CallsignRegistry>,
+    lastheard: Arc<LastHeard>,
+    status: Arc<StatusUpdater>,
 
     user_ssrc_map: HashMap<u64, u32>,
     ssrc_map: HashMap<u32, UserData>,
+    /// When the member of a user was last fetched
+    refreshed: HashMap<UserId, Instant>,
     cur_ssrc: Option<u32>,
     timeout_counter: u32,
+    /// The transmission to RF in progress, logged when it ends
+    transmission: Option<Transmission>,
 }
 
-impl USRPEventHandlerData {
+impl BridgeEventHandlerData {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        client: Arc<USRPClient>,
+        client: Arc<dyn VoiceBackend>,
         guild_id: GuildId,
         http: Arc<Http>,
         cache: Arc<Cache>,
+        data: &Data,
+        settings: Arc<GuildSettings>,
+        status: Arc<StatusUpdater>,
+        resampler: MonoResampler,
+        #[cfg(feature = "listen")] listen: Arc<ListenMixer>,
     ) -> Self {
-        let params = SincInterpolationParameters {
-            sinc_len: 256,
-            f_cutoff: 0.95,
-            interpolation: SincInterpolationType::Linear,
-            oversampling_factor: 256,
-            window: WindowFunction::BlackmanHarris2,
-        };
-
-        let resampler = SincFixedIn::new(8000 as f64 / 48000 as f64, 2.0, params, 960, 1).unwrap();
+        let config = &data.config;
 
         Self {
             client,
             cache,
             http,
             resampler,
+            #[cfg(feature = "listen")]
+            listen,
+            channel_mode: config.channel_mode,
+            require_license: config.require_license,
+            licensed_role: config.licensed_role,
+            require_control_op: config.require_control_op,
 
             guild_id,
+            settings,
+            radioid: data.radioid.clone(),
+            registry: data.registry.clone(),
+            lastheard: data.lastheard.clone(),
+            status,
             user_ssrc_map: HashMap::new(),
             ssrc_map: HashMap::new(),
+            refreshed: HashMap::new(),
             cur_ssrc: None,
             timeout_counter: 0,
+            transmission: None,
         }
     }
 
     fn ssrc_to_user(&self, ssrc: u32) -> Option<&UserData> {
         self.ssrc_map.get(&ssrc)
     }
+
+    /// Whether audio from this SSRC may be sent to RF
+    fn can_reach_rf(&self, ssrc: u32) -> bool {
+        if self.require_control_op && !self.settings.control_op_present() {
+            return false;
+        }
+        self.ssrc_to_user(ssrc)
+            .is_some_and(|user| user.can_transmit && !self.settings.user(user.id).rf_muted)
+    }
+
+    /// Metadata sent to RF when a user starts transmitting
+    fn talker_info(&self, user: &UserData) -> Talker {
+        let base = Callsign::parse(&user.callsign)
+            .map(|x| x.base)
+            .unwrap_or_else(|| user.callsign.clone());
+        let dmr_id = if base.is_empty() {
+            None
+        } else {
+            self.radioid
+                .read()
+                .unwrap()
+                .by_callsign(&base)
+                .first()
+                .map(|record| record.dmr_id)
+        };
+        Talker {
+            callsign: user.callsign.clone(),
+            dmr_id,
+            talkgroup: None,
+            name: Some(user.name.clone()),
+        }
+    }
+
+    /// Log the transmission to RF in progress and unkey RF
+    async fn end_transmission(&mut self) {
+        self.status.set_discord(None);
+        if let Some(mut transmission) = self.transmission.take() {
+            transmission.end = Utc::now();
+            self.lastheard.record(transmission);
+        }
+        let _ = self.client.send(VoicePacket::End).await;
+    }
+
+    /// Callsign and licence of a member, a registered callsign wins over one
+    /// found in the nickname
+    fn user_data(&self, member: &Member) -> UserData {
+        let nick = member.nick.clone().unwrap_or(
+            member
+                .user
+                .global_name
+                .clone()
+                .unwrap_or(member.user.name.clone()),
+        );
+        let callsign = self
+            .registry
+            .get(member.user.id)
+            .map(|x| x.callsign)
+            .or_else(|| extract_callsign(&nick).map(|x| x.to_string()))
+            .unwrap_or_default();
+        let can_transmit = self.is_licensed(member, &callsign);
+        UserData {
+            callsign,
+            name: member.user.name.clone(),
+            id: member.user.id,
+            can_transmit,
+        }
+    }
+
+    /// Members need a callsign or the licensed role to transmit
+    fn is_licensed(&self, member: &Member, callsign: &str) -> bool {
+        !self.require_license
+            || !callsign.is_empty()
+            || self
+                .licensed_role
+                .is_some_and(|role| member.roles.contains(&role))
+    }
 }
 
-impl Drop for USRPEventHandlerData {
+impl Drop for BridgeEventHandlerData {
     fn drop(&mut self) {}
 }
 
-impl USRPEventHandler {
+impl BridgeEventHandler {
+    /// `resampler` converts 960 samples of Discord audio to the backend sample rate
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        client: Arc<USRPClient>,
+        client: Arc<dyn VoiceBackend>,
         guild_id: GuildId,
         http: Arc<Http>,
         cache: Arc<Cache>,
+        data: &Data,
+        settings: Arc<GuildSettings>,
+        status: Arc<StatusUpdater>,
+        resampler: MonoResampler,
+        #[cfg(feature = "listen")] listen: Arc<ListenMixer>,
     ) -> Self {
         Self {
-            inner: Arc::new(Mutex::new(USRPEventHandlerData::new(
-                client, guild_id, http, cache,
+            inner: Arc::new(Mutex::new(BridgeEventHandlerData::new(
+                client,
+                guild_id,
+                http,
+                cache,
+                data,
+                settings,
+                status,
+                resampler,
+                #[cfg(feature = "listen")]
+                listen,
             ))),
         }
     }
+
+    /// Recompute the callsign and licence of a member in the voice channel
+    /// after their roles, nickname or registered callsign changed
+    pub async fn refresh_member(&self, member: &Member) {
+        let mut data = self.inner.lock().await;
+        let Some(&ssrc) = data.user_ssrc_map.get(&member.user.id.get()) else {
+            return;
+        };
+        let user_data = data.user_data(member);
+        if data
+            .ssrc_map
+            .get(&ssrc)
+            .is_some_and(|x| x.can_transmit != user_data.can_transmit)
+        {
+            info!(
+                "{} ({}) with id: {} is now {}",
+                user_data.callsign,
+                user_data.name,
+                user_data.id,
+                if user_data.can_transmit {
+                    "licensed"
+                } else {
+                    "listen-only"
+                }
+            );
+        }
+        data.client.stats().set_user(
+            ssrc,
+            Some(format!("{} ({})", user_data.callsign, user_data.name)),
+        );
+        data.ssrc_map.insert(ssrc, user_data);
+    }
+
+    /// Stop a transmission to RF in progress, so RF is not left keyed
+    pub async fn end_transmission(&self) {
+        let mut data = self.inner.lock().await;
+        if data.cur_ssrc.take().is_some() {
+            data.end_transmission().await;
+        }
+    }
 }
 
 #[derive(Clone)]
-pub struct USRPEventHandler {
-    inner: Arc<Mutex<USRPEventHandlerData>>,
+pub struct BridgeEventHandler {
+    inner: Arc<Mutex<BridgeEventHandlerData>>,
 }
 
-impl Drop for USRPEventHandler {
+impl Drop for BridgeEventHandler {
     fn drop(&mut self) {}
 }
 
 #[async_trait]
-impl VoiceEventHandler for USRPEventHandler {
+impl VoiceEventHandler for BridgeEventHandler {
     async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
         use EventContext as Ctx;
         match ctx {
@@ -141,28 +316,37 @@ impl VoiceEventHandler for USRPEventHandler {
                     .map(|x| x.clone())?;
 
                 let member = &*(guild.member(&data.http, user_id.0).await.ok()?);
-
-                let nick = member.nick.clone().unwrap_or(
-                    member.user
-                        .global_name.clone()
-                        .unwrap_or(member.user.name.clone()),
-                ).clone();
-
-                let callsign = extract_callsign(&nick).unwrap_or("".to_string());
-                let name = member.user.name.clone();
-                let id = member.user.id;
-                let user_data = UserData {
-                    callsign,
-                    nick,
-                    name,
-                    id,
-                };
+                let user_data = data.user_data(member);
+                let (id, can_transmit) = (user_data.id, user_data.can_transmit);
+                data.refreshed.insert(id, Instant::now());
 
                 info!(
                     "{} ({}) with id: {} has connected",
                     user_data.callsign, user_data.name, user_data.id
                 );
 
+                if !can_transmit && data.settings.first_listen_only_notice(id) {
+                    info!(
+                        "{} ({}) with id: {} is listen-only, no callsign or licensed role",
+                        user_data.callsign, user_data.name, user_data.id
+                    );
+                    let http = data.http.clone();
+                    tokio::spawn(async move {
+                        let message = CreateMessage::new().content(
+                            "You are listen-only on the RF bridge: transmitting requires a \
+                             callsign, register yours with `/callsign set` or ask a moderator \
+                             for the licensed role.",
+                        );
+                        if let Err(e) = id.direct_message(&http, message).await {
+                            warn!("Failed to DM listen-only notice to {}: {:?}", id, e);
+                        }
+                    });
+                }
+
+                data.client.stats().set_user(
+                    *ssrc,
+                    Some(format!("{} ({})", user_data.callsign, user_data.name)),
+                );
                 data.ssrc_map.insert(*ssrc, user_data);
                 data.user_ssrc_map.insert(id.get(), *ssrc);
             }
@@ -173,16 +357,39 @@ impl VoiceEventHandler for USRPEventHandler {
             }) => {
                 let mut data = self.inner.lock().await;
 
+                // Fetch speaking members again now and then, off the audio path
+                let stale: Vec<_> = speaking
+                    .keys()
+                    .filter_map(|x| data.ssrc_to_user(*x))
+                    .map(|user| user.id)
+                    .filter(|id| {
+                        data.refreshed
+                            .get(id)
+                            .is_none_or(|x| x.elapsed() >= MEMBER_REFRESH)
+                    })
+                    .collect();
+                for id in stale {
+                    data.refreshed.insert(id, Instant::now());
+                    let (handler, guild_id, http) =
+                        (self.clone(), data.guild_id, data.http.clone());
+                    tokio::spawn(async move {
+                        match guild_id.member(&*http, id).await {
+                            Ok(member) => handler.refresh_member(&member).await,
+                            Err(e) => warn!("Failed to fetch member {}: {}", id, e),
+                        }
+                    });
+                }
+
                 let mut audio_vec = Vec::new();
 
                 let is_previously_transmitting = data.cur_ssrc.is_some();
 
                 // If we don't have a current SSRC, we'll just pick a random one.
                 if data.cur_ssrc.is_none() {
-                    // Filter ssrcs not known to be associated with a user
+                    // Filter ssrcs not known to be associated with a user, or muted on RF
                     let active_ssrcs: Vec<_> = speaking
                         .keys()
-                        .filter(|&x| data.ssrc_map.get(x).is_some())
+                        .filter(|&x| data.can_reach_rf(*x))
                         .cloned()
                         .collect();
                     data.cur_ssrc = active_ssrcs.choose(&mut rand::rng()).copied();
@@ -190,26 +397,38 @@ impl VoiceEventHandler for USRPEventHandler {
                 }
 
                 if let Some(cur_ssrc) = data.cur_ssrc {
+                    // A user muted mid transmission is treated as silent
                     let audio_data = speaking
                         .get(&cur_ssrc)
-                        .and_then(|packet| packet.decoded_voice.as_ref());
+                        .and_then(|packet| packet.decoded_voice.as_ref())
+                        .filter(|_| data.can_reach_rf(cur_ssrc));
 
                     if let Some(audio_data) = audio_data {
                         data.timeout_counter = 10;
                         // audio_data is L, R, L, R, merge it into a single channel
-                        audio_vec = audio_data
-                            .chunks_exact(2)
-                            .map(|x| (x[0] as f64 + x[1] as f64) / 65536.0)
+                        let volume = data
+                            .ssrc_to_user(cur_ssrc)
+                            .map(|user| data.settings.user(user.id).volume)
+                            .unwrap_or(1.0);
+                        audio_vec = downmix(audio_data, data.channel_mode)
+                            .into_iter()
+                            .map(|x| (x * volume).clamp(-1.0, 1.0))
                             .collect();
                     } else {
-                        data.timeout_counter -= 1;
+                        data.timeout_counter = data.timeout_counter.saturating_sub(1);
                         if data.timeout_counter == 0 {
-                            let user_data = data.ssrc_to_user(data.cur_ssrc.unwrap())?;
-                            info!(
-                                "{} ({}) with id: {} stopped transmitting",
-                                user_data.callsign, user_data.name, user_data.id
-                            );
+                            // Cleared first, a user gone from the map must not keep RF keyed
                             data.cur_ssrc = None;
+                            if let Some(user_data) = data.ssrc_to_user(cur_ssrc) {
+                                info!(
+                                    bridge = data.guild_id.get(),
+                                    direction = Direction::ToRf.key(),
+                                    callsign = user_data.callsign.as_str(),
+                                    user_id = user_data.id.get();
+                                    "{} ({}) with id: {} stopped transmitting",
+                                    user_data.callsign, user_data.name, user_data.id
+                                );
+                            }
                         }
                     }
                 }
@@ -218,51 +437,69 @@ impl VoiceEventHandler for USRPEventHandler {
 
                 // Edge detector
                 if !is_previously_transmitting && is_currently_transmitting {
-                    let _ = data
-                        .client
-                        .send(USRPPacket::Start(StartPacket {
-                            sequence_number: data.client.get_and_increment_sequence_number(),
-                        }))
-                        .await;
                     let user_data = data.ssrc_to_user(data.cur_ssrc.unwrap())?;
+                    let info = data.talker_info(user_data);
                     info!(
+                        bridge = data.guild_id.get(),
+                        direction = Direction::ToRf.key(),
+                        callsign = user_data.callsign.as_str(),
+                        user_id = user_data.id.get(),
+                        dmr_id = info.dmr_id;
                         "{} ({}) with id: {} started transmitting",
                         user_data.callsign, user_data.name, user_data.id
                     );
+                    let transmission = Transmission {
+                        callsign: user_data.callsign.clone(),
+                        user_id: Some(user_data.id.get()),
+                        user_name: Some(user_data.name.clone()),
+                        dmr_id: info.dmr_id,
+                        talkgroup: info.talkgroup,
+                        ..Transmission::start(data.guild_id.get(), Direction::ToRf)
+                    };
+                    let talker = if transmission.callsign.is_empty() {
+                        user_data.name.clone()
+                    } else {
+                        transmission.callsign.clone()
+                    };
+                    data.status.set_discord(Some(talker));
+                    data.transmission = Some(transmission);
+                    let _ = data.client.send(VoicePacket::Start(Some(info))).await;
                 } else if is_previously_transmitting && !is_currently_transmitting {
-                    let _ = data
-                        .client
-                        .send(USRPPacket::End(EndPacket {
-                            sequence_number: data.client.get_and_increment_sequence_number(),
-                        }))
-                        .await;
+                    data.end_transmission().await;
                 }
 
                 if audio_vec.len() == 960 {
-                    let audio_output: Vec<_> = data
-                        .resampler
-                        .process(&[&audio_vec], None)
-                        .ok()?
-                        .iter()
-                        .next()?
+                    let peak = audio_vec.iter().fold(0.0f64, |a, x| a.max(x.abs()));
+                    data.client
+                        .stats()
+                        .audio_level(Direction::ToRf, peak as f32);
+                    #[cfg(feature = "listen")]
+                    {
+                        let audio: Vec<_> = audio_vec.iter().map(|&x| x as f32).collect();
+                        data.listen.push(Direction::ToRf, &audio);
+                    }
+                    let started = Instant::now();
+                    let resampled = data.resampler.process(audio_vec);
+                    data.client.stats().resampled(started.elapsed());
+                    let audio_output: Vec<_> = resampled?
                         .into_iter()
                         .map(|f| (f * 32768.0) as i16)
                         .collect();
 
-                    let _ = data
-                        .client
-                        .send(USRPPacket::Audio(AudioPacket {
-                            sequence_number: data.client.get_and_increment_sequence_number(),
-                            transmit: true,
-                            audio: audio_output,
-                        }))
-                        .await;
+                    let _ = data.client.send(VoicePacket::Audio(audio_output)).await;
                 }
             }
             Ctx::ClientDisconnect(ClientDisconnect { user_id, .. }) => {
                 let mut data = self.inner.lock().await;
                 let ssrc = data.user_ssrc_map.remove(&user_id.0)?;
                 let user_data = data.ssrc_map.remove(&ssrc)?;
+                data.refreshed.remove(&user_data.id);
+                data.client.stats().set_user(ssrc, None);
+                // A talker leaving mid transmission unkeys RF right away
+                if data.cur_ssrc == Some(ssrc) {
+                    data.cur_ssrc = None;
+                    data.end_transmission().await;
+                }
 
                 info!(
                     "{} ({}) with id: {} has disconnected",
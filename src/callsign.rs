@@ -0,0 +1,470 @@
+use std::{fmt, str::FromStr};
+
+/// ITU international call sign series allocations (Radio Regulations, Appendix 42).
+///
+/// Each entry is an inclusive range of the leading characters of a call sign.
+/// Single character entries cover the whole series, e.g. `K` is KAA-KZZ.
+/// Three character entries take precedence over shorter ones.
+const ITU_ALLOCATIONS: &[(&str, &str, &str)] = &[
+    ("3DA", "3DM", "Eswatini"),
+    ("3DN", "3DZ", "Fiji"),
+    ("3D2", "3D2", "Fiji"),
+    ("SSA", "SSM", "Egypt"),
+    ("SSN", "STZ", "Sudan"),
+    ("2", "2", "United Kingdom"),
+    ("3A", "3A", "Monaco"),
+    ("3B", "3B", "Mauritius"),
+    ("3C", "3C", "Equatorial Guinea"),
+    ("3E", "3F", "Panama"),
+    ("3G", "3G", "Chile"),
+    ("3H", "3U", "China"),
+    ("3V", "3V", "Tunisia"),
+    ("3W", "3W", "Vietnam"),
+    ("3X", "3X", "Guinea"),
+    ("3Y", "3Y", "Norway"),
+    ("3Z", "3Z", "Poland"),
+    ("4A", "4C", "Mexico"),
+    ("4D", "4I", "Philippines"),
+    ("4J", "4K", "Azerbaijan"),
+    ("4L", "4L", "Georgia"),
+    ("4M", "4M", "Venezuela"),
+    ("4O", "4O", "Montenegro"),
+    ("4P", "4S", "Sri Lanka"),
+    ("4T", "4T", "Peru"),
+    ("4U", "4U", "United Nations"),
+    ("4V", "4V", "Haiti"),
+    ("4W", "4W", "Timor-Leste"),
+    ("4X", "4X", "Israel"),
+    ("4Y", "4Y", "ICAO"),
+    ("4Z", "4Z", "Israel"),
+    ("5A", "5A", "Libya"),
+    ("5B", "5B", "Cyprus"),
+    ("5C", "5G", "Morocco"),
+    ("5H", "5I", "Tanzania"),
+    ("5J", "5K", "Colombia"),
+    ("5L", "5M", "Liberia"),
+    ("5N", "5O", "Nigeria"),
+    ("5P", "5Q", "Denmark"),
+    ("5R", "5S", "Madagascar"),
+    ("5T", "5T", "Mauritania"),
+    ("5U", "5U", "Niger"),
+    ("5V", "5V", "Togo"),
+    ("5W", "5W", "Samoa"),
+    ("5X", "5X", "Uganda"),
+    ("5Y", "5Z", "Kenya"),
+    ("6A", "6B", "Egypt"),
+    ("6C", "6C", "Syria"),
+    ("6D", "6J", "Mexico"),
+    ("6K", "6N", "South Korea"),
+    ("6O", "6O", "Somalia"),
+    ("6P", "6S", "Pakistan"),
+    ("6T", "6U", "Sudan"),
+    ("6V", "6W", "Senegal"),
+    ("6X", "6X", "Madagascar"),
+    ("6Y", "6Y", "Jamaica"),
+    ("6Z", "6Z", "Liberia"),
+    ("7A", "7I", "Indonesia"),
+    ("7J", "7N", "Japan"),
+    ("7O", "7O", "Yemen"),
+    ("7P", "7P", "Lesotho"),
+    ("7Q", "7Q", "Malawi"),
+    ("7R", "7R", "Algeria"),
+    ("7S", "7S", "Sweden"),
+    ("7T", "7Y", "Algeria"),
+    ("7Z", "7Z", "Saudi Arabia"),
+    ("8A", "8I", "Indonesia"),
+    ("8J", "8N", "Japan"),
+    ("8O", "8O", "Botswana"),
+    ("8P", "8P", "Barbados"),
+    ("8Q", "8Q", "Maldives"),
+    ("8R", "8R", "Guyana"),
+    ("8S", "8S", "Sweden"),
+    ("8T", "8Y", "India"),
+    ("8Z", "8Z", "Saudi Arabia"),
+    ("9A", "9A", "Croatia"),
+    ("9B", "9D", "Iran"),
+    ("9E", "9F", "Ethiopia"),
+    ("9G", "9G", "Ghana"),
+    ("9H", "9H", "Malta"),
+    ("9I", "9J", "Zambia"),
+    ("9K", "9K", "Kuwait"),
+    ("9L", "9L", "Sierra Leone"),
+    ("9M", "9M", "Malaysia"),
+    ("9N", "9N", "Nepal"),
+    ("9O", "9T", "DR Congo"),
+    ("9U", "9U", "Burundi"),
+    ("9V", "9V", "Singapore"),
+    ("9W", "9W", "Malaysia"),
+    ("9X", "9X", "Rwanda"),
+    ("9Y", "9Z", "Trinidad and Tobago"),
+    ("A2", "A2", "Botswana"),
+    ("A3", "A3", "Tonga"),
+    ("A4", "A4", "Oman"),
+    ("A5", "A5", "Bhutan"),
+    ("A6", "A6", "United Arab Emirates"),
+    ("A7", "A7", "Qatar"),
+    ("A8", "A8", "Liberia"),
+    ("A9", "A9", "Bahrain"),
+    ("AA", "AL", "United States"),
+    ("AM", "AO", "Spain"),
+    ("AP", "AS", "Pakistan"),
+    ("AT", "AW", "India"),
+    ("AX", "AX", "Australia"),
+    ("AY", "AZ", "Argentina"),
+    ("B", "B", "China"),
+    ("C2", "C2", "Nauru"),
+    ("C3", "C3", "Andorra"),
+    ("C4", "C4", "Cyprus"),
+    ("C5", "C5", "Gambia"),
+    ("C6", "C6", "Bahamas"),
+    ("C7", "C7", "WMO"),
+    ("C8", "C9", "Mozambique"),
+    ("CA", "CE", "Chile"),
+    ("CF", "CK", "Canada"),
+    ("CL", "CM", "Cuba"),
+    ("CN", "CN", "Morocco"),
+    ("CO", "CO", "Cuba"),
+    ("CP", "CP", "Bolivia"),
+    ("CQ", "CU", "Portugal"),
+    ("CV", "CX", "Uruguay"),
+    ("CY", "CZ", "Canada"),
+    ("D2", "D3", "Angola"),
+    ("D4", "D4", "Cape Verde"),
+    ("D5", "D5", "Liberia"),
+    ("D6", "D6", "Comoros"),
+    ("D7", "D9", "South Korea"),
+    ("DA", "DR", "Germany"),
+    ("DS", "DT", "South Korea"),
+    ("DU", "DZ", "Philippines"),
+    ("E2", "E2", "Thailand"),
+    ("E3", "E3", "Eritrea"),
+    ("E4", "E4", "Palestine"),
+    ("E5", "E5", "Cook Islands"),
+    ("E6", "E6", "Niue"),
+    ("E7", "E7", "Bosnia and Herzegovina"),
+    ("EA", "EH", "Spain"),
+    ("EI", "EJ", "Ireland"),
+    ("EK", "EK", "Armenia"),
+    ("EL", "EL", "Liberia"),
+    ("EM", "EO", "Ukraine"),
+    ("EP", "EQ", "Iran"),
+    ("ER", "ER", "Moldova"),
+    ("ES", "ES", "Estonia"),
+    ("ET", "ET", "Ethiopia"),
+    ("EU", "EW", "Belarus"),
+    ("EX", "EX", "Kyrgyzstan"),
+    ("EY", "EY", "Tajikistan"),
+    ("EZ", "EZ", "Turkmenistan"),
+    ("F", "F", "France"),
+    ("G", "G", "United Kingdom"),
+    ("H2", "H2", "Cyprus"),
+    ("H3", "H3", "Panama"),
+    ("H4", "H4", "Solomon Islands"),
+    ("H6", "H7", "Nicaragua"),
+    ("H8", "H9", "Panama"),
+    ("HA", "HA", "Hungary"),
+    ("HB", "HB", "Switzerland"),
+    ("HC", "HD", "Ecuador"),
+    ("HE", "HE", "Switzerland"),
+    ("HF", "HF", "Poland"),
+    ("HG", "HG", "Hungary"),
+    ("HH", "HH", "Haiti"),
+    ("HI", "HI", "Dominican Republic"),
+    ("HJ", "HK", "Colombia"),
+    ("HL", "HL", "South Korea"),
+    ("HM", "HM", "North Korea"),
+    ("HN", "HN", "Iraq"),
+    ("HO", "HP", "Panama"),
+    ("HQ", "HR", "Honduras"),
+    ("HS", "HS", "Thailand"),
+    ("HT", "HT", "Nicaragua"),
+    ("HU", "HU", "El Salvador"),
+    ("HV", "HV", "Vatican"),
+    ("HW", "HY", "France"),
+    ("HZ", "HZ", "Saudi Arabia"),
+    ("I", "I", "Italy"),
+    ("J2", "J2", "Djibouti"),
+    ("J3", "J3", "Grenada"),
+    ("J4", "J4", "Greece"),
+    ("J5", "J5", "Guinea-Bissau"),
+    ("J6", "J6", "Saint Lucia"),
+    ("J7", "J7", "Dominica"),
+    ("J8", "J8", "Saint Vincent and the Grenadines"),
+    ("JA", "JS", "Japan"),
+    ("JT", "JV", "Mongolia"),
+    ("JW", "JX", "Norway"),
+    ("JY", "JY", "Jordan"),
+    ("JZ", "JZ", "Indonesia"),
+    ("K", "K", "United States"),
+    ("L2", "L9", "Argentina"),
+    ("LA", "LN", "Norway"),
+    ("LO", "LW", "Argentina"),
+    ("LX", "LX", "Luxembourg"),
+    ("LY", "LY", "Lithuania"),
+    ("LZ", "LZ", "Bulgaria"),
+    ("M", "M", "United Kingdom"),
+    ("N", "N", "United States"),
+    ("OA", "OC", "Peru"),
+    ("OD", "OD", "Lebanon"),
+    ("OE", "OE", "Austria"),
+    ("OF", "OJ", "Finland"),
+    ("OK", "OL", "Czech Republic"),
+    ("OM", "OM", "Slovakia"),
+    ("ON", "OT", "Belgium"),
+    ("OU", "OZ", "Denmark"),
+    ("P2", "P2", "Papua New Guinea"),
+    ("P3", "P3", "Cyprus"),
+    ("P4", "P4", "Aruba"),
+    ("P5", "P9", "North Korea"),
+    ("PA", "PI", "Netherlands"),
+    ("PJ", "PJ", "Netherlands"),
+    ("PK", "PO", "Indonesia"),
+    ("PP", "PY", "Brazil"),
+    ("PZ", "PZ", "Suriname"),
+    ("R", "R", "Russia"),
+    ("S2", "S3", "Bangladesh"),
+    ("S5", "S5", "Slovenia"),
+    ("S6", "S6", "Singapore"),
+    ("S7", "S7", "Seychelles"),
+    ("S8", "S8", "South Africa"),
+    ("S9", "S9", "Sao Tome and Principe"),
+    ("SA", "SM", "Sweden"),
+    ("SN", "SR", "Poland"),
+    ("SU", "SU", "Egypt"),
+    ("SV", "SZ", "Greece"),
+    ("T2", "T2", "Tuvalu"),
+    ("T3", "T3", "Kiribati"),
+    ("T4", "T4", "Cuba"),
+    ("T5", "T5", "Somalia"),
+    ("T6", "T6", "Afghanistan"),
+    ("T7", "T7", "San Marino"),
+    ("T8", "T8", "Palau"),
+    ("TA", "TC", "Turkey"),
+    ("TD", "TD", "Guatemala"),
+    ("TE", "TE", "Costa Rica"),
+    ("TF", "TF", "Iceland"),
+    ("TG", "TG", "Guatemala"),
+    ("TH", "TH", "France"),
+    ("TI", "TI", "Costa Rica"),
+    ("TJ", "TJ", "Cameroon"),
+    ("TK", "TK", "France"),
+    ("TL", "TL", "Central African Republic"),
+    ("TM", "TM", "France"),
+    ("TN", "TN", "Congo"),
+    ("TO", "TQ", "France"),
+    ("TR", "TR", "Gabon"),
+    ("TS", "TS", "Tunisia"),
+    ("TT", "TT", "Chad"),
+    ("TU", "TU", "Cote d'Ivoire"),
+    ("TV", "TX", "France"),
+    ("TY", "TY", "Benin"),
+    ("TZ", "TZ", "Mali"),
+    ("UA", "UI", "Russia"),
+    ("UJ", "UM", "Uzbekistan"),
+    ("UN", "UQ", "Kazakhstan"),
+    ("UR", "UZ", "Ukraine"),
+    ("V2", "V2", "Antigua and Barbuda"),
+    ("V3", "V3", "Belize"),
+    ("V4", "V4", "Saint Kitts and Nevis"),
+    ("V5", "V5", "Namibia"),
+    ("V6", "V6", "Micronesia"),
+    ("V7", "V7", "Marshall Islands"),
+    ("V8", "V8", "Brunei"),
+    ("VA", "VG", "Canada"),
+    ("VH", "VN", "Australia"),
+    ("VO", "VO", "Canada"),
+    ("VP", "VQ", "United Kingdom"),
+    ("VR", "VR", "Hong Kong"),
+    ("VS", "VS", "United Kingdom"),
+    ("VT", "VW", "India"),
+    ("VX", "VY", "Canada"),
+    ("VZ", "VZ", "Australia"),
+    ("W", "W", "United States"),
+    ("XA", "XI", "Mexico"),
+    ("XJ", "XO", "Canada"),
+    ("XP", "XP", "Denmark"),
+    ("XQ", "XR", "Chile"),
+    ("XS", "XS", "China"),
+    ("XT", "XT", "Burkina Faso"),
+    ("XU", "XU", "Cambodia"),
+    ("XV", "XV", "Vietnam"),
+    ("XW", "XW", "Laos"),
+    ("XX", "XX", "Macao"),
+    ("XY", "XZ", "Myanmar"),
+    ("Y2", "Y9", "Germany"),
+    ("YA", "YA", "Afghanistan"),
+    ("YB", "YH", "Indonesia"),
+    ("YI", "YI", "Iraq"),
+    ("YJ", "YJ", "Vanuatu"),
+    ("YK", "YK", "Syria"),
+    ("YL", "YL", "Latvia"),
+    ("YM", "YM", "Turkey"),
+    ("YN", "YN", "Nicaragua"),
+    ("YO", "YR", "Romania"),
+    ("YS", "YS", "El Salvador"),
+    ("YT", "YU", "Serbia"),
+    ("YV", "YY", "Venezuela"),
+    ("YZ", "YZ", "Serbia"),
+    ("Z2", "Z2", "Zimbabwe"),
+    ("Z3", "Z3", "North Macedonia"),
+    ("Z8", "Z8", "South Sudan"),
+    ("ZA", "ZA", "Albania"),
+    ("ZB", "ZJ", "United Kingdom"),
+    ("ZK", "ZM", "New Zealand"),
+    ("ZN", "ZO", "United Kingdom"),
+    ("ZP", "ZP", "Paraguay"),
+    ("ZQ", "ZQ", "United Kingdom"),
+    ("ZR", "ZU", "South Africa"),
+    ("ZV", "ZZ", "Brazil"),
+];
+
+/// Operating suffixes that are not a location prefix
+const OPERATING_SUFFIXES: &[&str] = &["P", "M", "MM", "AM", "A", "QRP", "QRPP", "LH"];
+
+/// Country allocated the call sign series `call` starts with
+pub fn itu_country(call: &str) -> Option<&'static str> {
+    for len in [3, 2, 1] {
+        let Some(key) = call.get(..len) else {
+            continue;
+        };
+        let found = ITU_ALLOCATIONS
+            .iter()
+            .filter(|(from, _, _)| from.len() == len)
+            .find(|(from, to, _)| *from <= key && key <= *to);
+        if let Some((_, _, country)) = found {
+            return Some(country);
+        }
+    }
+    None
+}
+
+/// An amateur radio call sign, e.g. `DL/W1AW/P`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Callsign {
+    /// The call sign as issued, e.g. `W1AW`
+    pub base: String,
+    /// Location prefix when operating abroad or in another district, e.g. `DL` or `VE3`
+    pub prefix: Option<String>,
+    /// Operating suffix, e.g. `P`, `M` or `QRP`
+    pub suffix: Option<String>,
+    /// Country of the location prefix, or of the base call without one
+    pub country: &'static str,
+}
+
+/// Country of a base call sign: a 1-3 character prefix, a digit and 1-4 letters
+fn base_country(call: &str) -> Option<&'static str> {
+    let digit = call.rfind(|c: char| c.is_ascii_digit())?;
+    let (prefix, suffix) = (&call[..digit], &call[digit + 1..]);
+    let is_call = (1..=3).contains(&prefix.len())
+        && prefix.chars().all(|c| c.is_ascii_alphanumeric())
+        && prefix.chars().any(|c| c.is_ascii_alphabetic())
+        && (1..=4).contains(&suffix.len())
+        && suffix.chars().all(|c| c.is_ascii_alphabetic());
+    if !is_call {
+        return None;
+    }
+    // The district digit is only part of the series for two character prefixes such as 3D2,
+    // otherwise C3PO would pass as an Andorran call
+    if prefix.len() == 2 {
+        itu_country(&call[..=digit])
+    } else {
+        itu_country(prefix)
+    }
+}
+
+/// A location prefix such as `DL`, `VE3` or `KH6`: a 1-2 character ITU series
+/// and an optional district digit
+fn is_location_prefix(part: &str) -> bool {
+    let series = part
+        .strip_suffix(|c: char| c.is_ascii_digit())
+        .unwrap_or(part);
+    (1..=2).contains(&series.len())
+        && series.chars().all(|c| c.is_ascii_alphanumeric())
+        && series.chars().any(|c| c.is_ascii_alphabetic())
+        && itu_country(series).is_some()
+}
+
+impl Callsign {
+    /// Parse a single call sign, case insensitive
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.to_ascii_uppercase();
+        let parts: Vec<&str> = text.split('/').collect();
+        if parts.iter().any(|x| x.is_empty()) {
+            return None;
+        }
+
+        // The base call is the longest part shaped like a call sign
+        let base_index = (0..parts.len())
+            .filter(|&i| base_country(parts[i]).is_some())
+            .max_by_key(|&i| parts[i].len())?;
+        let base = parts[base_index];
+
+        let mut prefix = None;
+        let mut suffix = None;
+        for (i, &part) in parts.iter().enumerate() {
+            if i == base_index {
+                continue;
+            }
+            let is_suffix = i > base_index
+                && (OPERATING_SUFFIXES.contains(&part)
+                    || (part.len() == 1 && part.chars().all(|c| c.is_ascii_digit())));
+            if suffix.is_some() {
+                // The operating suffix always comes last
+                return None;
+            } else if is_suffix {
+                suffix = Some(part.to_string());
+            } else if is_location_prefix(part) && prefix.is_none() {
+                prefix = Some(part.to_string());
+            } else {
+                return None;
+            }
+        }
+
+        let country = match &prefix {
+            Some(prefix) => itu_country(prefix)?,
+            None => base_country(base)?,
+        };
+        Some(Self {
+            base: base.to_string(),
+            prefix,
+            suffix,
+            country,
+        })
+    }
+}
+
+impl FromStr for Callsign {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s).ok_or_else(|| format!("Invalid callsign: {}", s))
+    }
+}
+
+impl fmt::Display for Callsign {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(prefix) = &self.prefix {
+            write!(f, "{}/", prefix)?;
+        }
+        write!(f, "{}", self.base)?;
+        if let Some(suffix) = &self.suffix {
+            write!(f, "/{}", suffix)?;
+        }
+        Ok(())
+    }
+}
+
+/// Find the first call sign in free text such as a Discord nickname
+///
+/// Words like `w8ing` or `g2g` are shaped like call signs, so a lowercase call
+/// is only taken when it stands alone between delimiters such as `|` or `()`
+pub fn extract_callsign(text: &str) -> Option<Callsign> {
+    text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '/' || c.is_whitespace()))
+        .flat_map(|segment| {
+            let words: Vec<_> = segment.split_whitespace().collect();
+            let alone = words.len() == 1;
+            words.into_iter().map(move |word| (word, alone))
+        })
+        .filter(|(word, alone)| *alone || !word.chars().any(|c| c.is_ascii_lowercase()))
+        .find_map(|(word, _)| Callsign::parse(word))
+}
@@ -1,37 +1,49 @@
 use chrono::prelude::Utc;
-use log::{debug, info};
-use poise::serenity_prelude as serenity;
-use rubato::{
-    Resampler, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction,
-};
-use serenity::{all::EditChannel, prelude::Mentionable};
-use songbird::{
-    input::{AsyncAdapterStream, AsyncReadOnlySource, RawAdapter},
-    CoreEvent,
+use discord_bridge::{
+    callsign::{extract_callsign, Callsign},
+    radioid::RadioIdDatabase,
+    registry::RegistryError,
 };
-use std::sync::Arc;
-use tokio::io::AsyncWriteExt;
-
-use crate::{Context, Data, Error};
+use log::{info, warn};
+use poise::serenity_prelude as serenity;
+use serenity::prelude::Mentionable;
+use std::time::{Duration, Instant};
 
-use crate::{
-    bridge::USRPEventHandler,
-    usrp::{packets::USRPPacket, USRPClient},
-};
+use crate::{control_op, session, usrp::SAMPLE_RATES, Context, Error};
 
 #[poise::command(slash_command)]
 pub async fn data(
     ctx: Context<'_>,
     #[description = "user"] user: serenity::Member,
 ) -> Result<(), Error> {
-    println!("{:?}", user);
-    //user.nick.unwrap_or(user.user.name.clone())
+    let registered = ctx
+        .data()
+        .registry
+        .get(user.user.id)
+        .and_then(|x| Callsign::parse(&x.callsign));
     let nick = user
         .nick
         .unwrap_or(user.user.global_name.unwrap_or(user.user.name));
-    match crate::util::extract_callsign(&nick) {
+    match registered.or_else(|| extract_callsign(&nick)) {
         Some(callsign) => {
-            ctx.say(format!("Callsign: {}", callsign)).await?;
+            let records: Vec<_> = ctx
+                .data()
+                .radioid
+                .read()
+                .unwrap()
+                .by_callsign(&callsign.base)
+                .to_vec();
+            let mut reply = format!(
+                "Callsign: {}\nBase call: {}\nCountry: {}",
+                callsign, callsign.base, callsign.country
+            );
+            for record in records {
+                reply += &format!(
+                    "\nDMR ID: {} - {}, {}, {}, {}",
+                    record.dmr_id, record.name, record.city, record.state, record.country
+                );
+            }
+            ctx.say(reply).await?;
         }
         None => {
             ctx.say("No callsign found").await?;
@@ -47,164 +59,634 @@ pub async fn join(
     #[description = "Selected channel"]
     #[channel_types("Voice")]
     channel: serenity::GuildChannel,
+    #[description = "USRP sample rate, 8000 (slin) or 16000 (slin16), kept for later joins"]
+    sample_rate: Option<usize>,
 ) -> Result<(), Error> {
-    let guild = ctx.guild().ok_or("No guild?")?.clone();
-    let guild_id = guild.id;
+    let guild_id = ctx.guild_id().ok_or("No guild?")?;
+    if let Some(sample_rate) = sample_rate {
+        if !SAMPLE_RATES.contains(&sample_rate) {
+            ctx.say("The sample rate must be 8000 or 16000").await?;
+            return Ok(());
+        }
+        let settings = ctx.data().guild_settings(guild_id).await;
+        settings.set_usrp_sample_rate(sample_rate);
+    }
 
-    let serenity_context = ctx.serenity_context();
-    let manager = songbird::get(serenity_context)
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
+    match session::join(ctx.serenity_context(), ctx.data(), guild_id, channel.id).await {
+        Ok(()) => {
+            ctx.say(&format!("Joined {}", channel.mention())).await?;
+        }
+        Err(e) => {
+            warn!("Failed to join {}: {}", channel.id, e);
+            ctx.say(format!("Error joining the channel: {}", e)).await?;
+        }
+    }
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+pub async fn leave(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("No guild?")?;
+
+    match session::leave(ctx.serenity_context(), ctx.data(), guild_id).await {
+        Ok(Some(channel_id)) => {
+            ctx.say(&format!("Left {}", channel_id.mention())).await?;
+        }
+        Ok(None) => {
+            ctx.reply("⚠️ Not in a voice channel").await?;
+        }
+        Err(e) => {
+            warn!("Failed to leave cleanly in guild {}: {}", guild_id, e);
+            ctx.reply(format!("⚠️ Failed to leave cleanly: {}", e))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Set the volume of a user on the RF side
+#[poise::command(slash_command, guild_only, required_permissions = "MUTE_MEMBERS")]
+pub async fn volume(
+    ctx: Context<'_>,
+    #[description = "user"] user: serenity::Member,
+    #[description = "volume in percent"]
+    #[min = 0]
+    #[max = 400]
+    percent: u32,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("No guild?")?;
+    let settings = ctx.data().guild_settings(guild_id).await;
+    settings.set_volume(user.user.id, percent as f64 / 100.0);
 
-    if let Ok(handler_lock) = manager.join(guild_id, channel.id).await {
-        let mut handler = handler_lock.lock().await;
+    info!(
+        "{} set RF volume of {} to {}%",
+        ctx.author().name,
+        user.user.name,
+        percent
+    );
+    ctx.say(format!(
+        "RF volume of {} set to {}%",
+        user.mention(),
+        percent
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Stop or resume a user's audio from reaching RF, they can still talk in Discord
+#[poise::command(slash_command, guild_only, required_permissions = "MUTE_MEMBERS")]
+pub async fn rfmute(
+    ctx: Context<'_>,
+    #[description = "user"] user: serenity::Member,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("No guild?")?;
+    let settings = ctx.data().guild_settings(guild_id).await;
+    let muted = settings.toggle_rf_mute(user.user.id);
 
-        let mut usrpclient = USRPClient::new(
-            "127.0.0.1:34001".parse().unwrap(),
-            "127.0.0.1:32001".parse().unwrap(),
-            None,
+    info!(
+        "{} {} {} on RF",
+        ctx.author().name,
+        if muted { "muted" } else { "unmuted" },
+        user.user.name
+    );
+    if muted {
+        ctx.say(format!("{} is muted on RF", user.mention()))
+            .await?;
+    } else {
+        ctx.say(format!("{} is no longer muted on RF", user.mention()))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Hand the control operator role over to another member
+#[poise::command(slash_command, guild_only)]
+pub async fn controlop(
+    ctx: Context<'_>,
+    #[description = "new control operator"] user: serenity::Member,
+) -> Result<(), Error> {
+    let role = ctx
+        .data()
+        .config
+        .control_op_role
+        .ok_or("No control operator role configured")?;
+    let guild_id = ctx.guild_id().ok_or("No guild?")?;
+    let author = ctx.author_member().await.ok_or("No user?")?;
+
+    let can_manage_roles = author.permissions.is_some_and(|x| x.manage_roles());
+    if !author.roles.contains(&role) && !can_manage_roles {
+        ctx.reply("⚠️ Only the control operator or a role manager can hand the role over")
+            .await?;
+        return Ok(());
+    }
+
+    let settings = ctx.data().guild_settings(guild_id).await;
+    let mut holders = settings.control_ops();
+    if let Some(guild) = ctx.guild() {
+        holders.extend(
+            guild
+                .members
+                .values()
+                .filter(|member| member.roles.contains(&role))
+                .map(|member| member.user.id),
         );
+    }
+    holders.sort();
+    holders.dedup();
 
-        usrpclient
-            .connect()
+    // Keep going when a holder cannot be demoted, the new control operator
+    // must get the role either way
+    let reason = format!("Control operator handed over by {}", ctx.author().name);
+    let mut failures = Vec::new();
+    for holder in holders.into_iter().filter(|&x| x != user.user.id) {
+        match ctx
+            .http()
+            .remove_member_role(guild_id, holder, role, Some(&reason))
             .await
-            .expect("Error connecting to USRP");
+        {
+            Ok(()) => settings.set_control_op(holder, false),
+            Err(e) => {
+                warn!(
+                    "Failed to remove the control operator role from {}: {}",
+                    holder, e
+                );
+                failures.push(format!(
+                    "could not remove the role from {}",
+                    holder.mention()
+                ));
+            }
+        }
+    }
+    let added = ctx
+        .http()
+        .add_member_role(guild_id, user.user.id, role, Some(&reason))
+        .await;
+    match &added {
+        Ok(()) => {
+            settings.set_control_op(user.user.id, true);
+            info!(
+                "{} handed the control operator role to {}",
+                ctx.author().name,
+                user.user.name
+            );
+        }
+        Err(e) => {
+            warn!(
+                "Failed to give the control operator role to {}: {}",
+                user.user.name, e
+            );
+            failures.push(format!("could not give the role to {}", user.mention()));
+        }
+    }
+    control_op::update_presence(ctx.serenity_context(), ctx.data(), guild_id, false).await;
 
-        let usrpclient = Arc::new(usrpclient);
+    let mut reply = if added.is_ok() {
+        format!("{} is now the control operator", user.mention())
+    } else {
+        "⚠️ The control operator role was not handed over".to_string()
+    };
+    for failure in failures {
+        reply += &format!("\n⚠️ {}", failure);
+    }
+    ctx.say(reply).await?;
+    Ok(())
+}
 
-        let usrp_channel = USRPEventHandler::new(
-            usrpclient.clone(),
-            guild_id,
-            serenity_context.http.clone(),
-            serenity_context.cache.clone(),
-        );
+/// Offline DMR ID database
+#[poise::command(slash_command, subcommands("reload"))]
+pub async fn radioid(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
 
-        //let builder = EditChannel::new().status("");
-        /*if let Err(why) = channel.edit(&serenity_context.http, builder).await {
-            // properly handle the error
-            println!("Error updating channel: {:?}", why);
-        }*/
+/// Reload the DMR ID database from its CSV file
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn reload(ctx: Context<'_>) -> Result<(), Error> {
+    let path = ctx
+        .data()
+        .config
+        .radioid_csv
+        .clone()
+        .ok_or("No RADIOID_CSV configured")?;
+    let database = tokio::task::spawn_blocking(move || RadioIdDatabase::load(path)).await?;
+    match database {
+        Ok(database) => {
+            let len = database.len();
+            *ctx.data().radioid.write().unwrap() = database;
+            info!("Reloaded {} DMR IDs", len);
+            ctx.say(format!("Loaded {} DMR IDs", len)).await?;
+        }
+        Err(e) => {
+            ctx.say(format!("Failed to load the DMR ID database: {}", e))
+                .await?;
+        }
+    }
+    Ok(())
+}
 
-        handler.add_global_event(CoreEvent::SpeakingStateUpdate.into(), usrp_channel.clone());
-        handler.add_global_event(CoreEvent::VoiceTick.into(), usrp_channel.clone());
-        handler.add_global_event(CoreEvent::ClientDisconnect.into(), usrp_channel.clone());
+/// Callsign registry, used instead of the callsign in your nickname
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands("set", "clear", "whois", "verify", "lock")
+)]
+pub async fn callsign(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
 
-        // 100ms of buffering
-        let (audio_receiver, mut audio_sender) = tokio::io::simplex(7680 * 5);
+/// Whether the author may change other users' registry entries. The registry
+/// is shared by every guild, so only the admins from `REGISTRY_ADMINS` qualify
+fn is_registry_admin(ctx: Context<'_>) -> bool {
+    ctx.data().config.registry_admins.contains(&ctx.author().id)
+}
 
-        let audio_stream = AsyncAdapterStream::new(
-            Box::new(AsyncReadOnlySource::new(Box::new(audio_receiver))),
-            7680 * 5,
-        );
-        let adapter = RawAdapter::new(audio_stream, 48000, 2);
-        let _ = handler.play_input(adapter.into());
+/// Let the bridge pick up a changed callsign right away
+async fn refresh_member(ctx: Context<'_>, user_id: serenity::UserId) {
+    let Some(guild_id) = ctx.guild_id() else {
+        return;
+    };
+    match guild_id.member(ctx, user_id).await {
+        Ok(member) => session::refresh_member(ctx.data(), &member).await,
+        Err(e) => warn!("Failed to fetch member {}: {}", user_id, e),
+    }
+}
 
-        info!(
-            "Connected to voice channel {} with id {}",
-            channel.name(),
-            channel.id.get()
-        );
+/// Register your callsign, admins can register one for another user
+#[poise::command(slash_command, guild_only)]
+pub async fn set(
+    ctx: Context<'_>,
+    #[description = "callsign"] callsign: String,
+    #[description = "user, admins only"] user: Option<serenity::User>,
+) -> Result<(), Error> {
+    let admin = is_registry_admin(ctx);
+    let user = match user {
+        Some(user) if user.id != ctx.author().id && !admin => {
+            ctx.reply("⚠️ Only admins can register a callsign for another user")
+                .await?;
+            return Ok(());
+        }
+        Some(user) => user,
+        None => ctx.author().clone(),
+    };
+    let Some(callsign) = Callsign::parse(&callsign) else {
+        ctx.reply(format!("⚠️ {} is not a valid callsign", callsign))
+            .await?;
+        return Ok(());
+    };
 
-        /*ctx.data()
-        .clients
-        .lock()
-        .await
-        .insert(guild_id.get(), usrpclient.clone());*/
-
-        let handler = Arc::downgrade(&handler_lock);
-        tokio::spawn(async move {
-            let params = SincInterpolationParameters {
-                sinc_len: 256,
-                f_cutoff: 0.95,
-                interpolation: SincInterpolationType::Linear,
-                oversampling_factor: 256,
-                window: WindowFunction::BlackmanHarris2,
-            };
-            let mut resampler =
-                SincFixedIn::new(48000 as f64 / 8000 as f64, 2.0, params, 160, 1).unwrap();
-
-            while let Some(_handler) = handler.upgrade() {
-                let packet = usrpclient.recv().await;
-                if let Some(packet) = packet {
-                    match packet {
-                        USRPPacket::Audio(packet) => {
-                            // Convert from i16 to f64
-                            let audio_vec: Vec<_> = packet
-                                .audio
-                                .into_iter()
-                                .map(|x| x as f64 / 32768.0)
-                                .collect();
-                            // Resample to 8kHz
-                            let audio_data: Vec<_> = resampler
-                                .process(&[&audio_vec], None)
-                                .into_iter()
-                                .flat_map(|x| x.into_iter())
-                                .take(1)
-                                .flat_map(|x| x.into_iter())
-                                .map(|x| x as f32)
-                                .map(|x| [x, x]) // Mono to stereo
-                                .flatten()
-                                .map(|x| x.to_le_bytes()) // Convert to byte stream
-                                .flatten()
-                                .collect();
-                            let _ = audio_sender.write(&audio_data).await;
-                        }
-                        USRPPacket::Start(_) => {}
-                        USRPPacket::End(_) => {}
-                        _ => {
-                            debug!("Unknown USRP packet");
-                        }
-                    }
+    match ctx.data().registry.set(user.id, &callsign, admin).await {
+        Ok(()) => {
+            info!(
+                "{} registered callsign {} for {}",
+                ctx.author().name,
+                callsign,
+                user.name
+            );
+            ctx.say(format!(
+                "{} is registered as {} ({})",
+                user.mention(),
+                callsign,
+                callsign.country
+            ))
+            .await?;
+            refresh_member(ctx, user.id).await;
+        }
+        Err(e @ (RegistryError::Locked | RegistryError::Taken(_))) => {
+            ctx.reply(format!("⚠️ Cannot register {}: {}", callsign, e))
+                .await?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+/// Remove your registered callsign, admins can remove another user's
+#[poise::command(slash_command, guild_only)]
+pub async fn clear(
+    ctx: Context<'_>,
+    #[description = "user, admins only"] user: Option<serenity::User>,
+) -> Result<(), Error> {
+    let admin = is_registry_admin(ctx);
+    let user = match user {
+        Some(user) if user.id != ctx.author().id && !admin => {
+            ctx.reply("⚠️ Only admins can clear another user's callsign")
+                .await?;
+            return Ok(());
+        }
+        Some(user) => user,
+        None => ctx.author().clone(),
+    };
+
+    match ctx.data().registry.clear(user.id, admin).await {
+        Ok(Some(entry)) => {
+            info!(
+                "{} cleared callsign {} of {}",
+                ctx.author().name,
+                entry.callsign,
+                user.name
+            );
+            ctx.say(format!(
+                "Removed {} from {}",
+                entry.callsign,
+                user.mention()
+            ))
+            .await?;
+            refresh_member(ctx, user.id).await;
+        }
+        Ok(None) => {
+            ctx.reply(format!("{} has no registered callsign", user.mention()))
+                .await?;
+        }
+        Err(e @ RegistryError::Locked) => {
+            ctx.reply(format!("⚠️ Cannot clear the callsign: {}", e))
+                .await?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+/// Show the callsign of a user
+#[poise::command(slash_command, guild_only)]
+pub async fn whois(
+    ctx: Context<'_>,
+    #[description = "user"] user: serenity::Member,
+) -> Result<(), Error> {
+    let reply = match ctx.data().registry.get(user.user.id) {
+        Some(entry) => format!(
+            "{} is registered as {}{}{}",
+            user.mention(),
+            entry.callsign,
+            if entry.verified { ", verified" } else { "" },
+            if entry.locked { ", locked" } else { "" }
+        ),
+        None => {
+            let nick = user.display_name();
+            match extract_callsign(nick) {
+                Some(callsign) => format!(
+                    "{} has no registered callsign, {} from the nickname",
+                    user.mention(),
+                    callsign
+                ),
+                None => format!("{} has no callsign", user.mention()),
+            }
+        }
+    };
+    ctx.say(reply).await?;
+    Ok(())
+}
+
+/// Mark a registered callsign as verified, or unverified
+#[poise::command(slash_command, guild_only)]
+pub async fn verify(
+    ctx: Context<'_>,
+    #[description = "user"] user: serenity::User,
+    #[description = "verified, defaults to true"] verified: Option<bool>,
+) -> Result<(), Error> {
+    if !is_registry_admin(ctx) {
+        ctx.reply("⚠️ Only registry admins can verify callsigns")
+            .await?;
+        return Ok(());
+    }
+    let verified = verified.unwrap_or(true);
+    match ctx.data().registry.set_verified(user.id, verified).await? {
+        Some(entry) => {
+            info!(
+                "{} {} callsign {} of {}",
+                ctx.author().name,
+                if verified { "verified" } else { "unverified" },
+                entry.callsign,
+                user.name
+            );
+            ctx.say(format!(
+                "{} of {} is {}",
+                entry.callsign,
+                user.mention(),
+                if verified {
+                    "verified"
                 } else {
-                    break;
+                    "no longer verified"
                 }
-            }
-        });
-        ctx.say(&format!("Joined {}", channel.mention())).await?;
-    } else {
-        ctx.say("Error joining the channel").await?;
+            ))
+            .await?;
+        }
+        None => {
+            ctx.reply(format!("{} has no registered callsign", user.mention()))
+                .await?;
+        }
     }
     Ok(())
 }
 
-#[poise::command(slash_command)]
-pub async fn leave(ctx: Context<'_>) -> Result<(), Error> {
-    let guild = ctx.guild().ok_or("No guild?")?.clone();
-    let guild_id = guild.id;
-    let user_id = ctx.author_member().await.ok_or("No user?")?.user.id;
-
-    let channel_id = guild
-        .voice_states
-        .get(&user_id)
-        .and_then(|voice_state| voice_state.channel_id)
-        .unwrap();
-
-    let serenity_context = ctx.serenity_context();
-    let manager = songbird::get(serenity_context)
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
-    let has_handler = manager.get(guild_id).is_some();
+/// Lock a registry entry so the user cannot change it, or unlock it
+#[poise::command(slash_command, guild_only)]
+pub async fn lock(
+    ctx: Context<'_>,
+    #[description = "user"] user: serenity::User,
+    #[description = "locked, defaults to true"] locked: Option<bool>,
+) -> Result<(), Error> {
+    if !is_registry_admin(ctx) {
+        ctx.reply("⚠️ Only registry admins can lock callsigns")
+            .await?;
+        return Ok(());
+    }
+    let locked = locked.unwrap_or(true);
+    match ctx.data().registry.set_locked(user.id, locked).await? {
+        Some(entry) => {
+            info!(
+                "{} {} callsign {} of {}",
+                ctx.author().name,
+                if locked { "locked" } else { "unlocked" },
+                entry.callsign,
+                user.name
+            );
+            ctx.say(format!(
+                "{} of {} is {}",
+                entry.callsign,
+                user.mention(),
+                if locked { "locked" } else { "unlocked" }
+            ))
+            .await?;
+        }
+        None => {
+            ctx.reply(format!("{} has no registered callsign", user.mention()))
+                .await?;
+        }
+    }
+    Ok(())
+}
 
-    info!(
-        "Disconnected from voice channel {} with id {}",
-        channel_id
-            .name(&serenity_context.http)
-            .await
-            .unwrap_or("{unknown}".to_string()),
-        channel_id.get()
+/// Show the most recent transmissions through the bridge
+#[poise::command(slash_command, guild_only)]
+pub async fn lastheard(
+    ctx: Context<'_>,
+    #[description = "number of transmissions, 10 by default"]
+    #[min = 1]
+    #[max = 25]
+    n: Option<usize>,
+    #[description = "attach the whole log as CSV"] export: Option<bool>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("No guild?")?;
+    let lastheard = ctx.data().lastheard.clone();
+    let transmissions = lastheard.recent(guild_id.get(), n.unwrap_or(10));
+
+    let description = if transmissions.is_empty() {
+        "Nothing heard yet".to_string()
+    } else {
+        transmissions
+            .iter()
+            .map(|x| {
+                let who = match x.user_id {
+                    Some(user_id) => format!("{} <@{}>", x.callsign, user_id),
+                    None => match x.dmr_id {
+                        Some(dmr_id) => format!("{} ({})", x.callsign, dmr_id),
+                        None => x.callsign.clone(),
+                    },
+                };
+                format!(
+                    "<t:{}:T> {} **{}** {:.1}s",
+                    x.start.timestamp(),
+                    x.direction,
+                    if who.is_empty() { "unknown" } else { &who },
+                    x.duration().num_milliseconds() as f64 / 1000.0
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let mut reply = poise::CreateReply::default().embed(
+        serenity::CreateEmbed::new()
+            .title("Last heard")
+            .description(description),
     );
 
-    if has_handler {
-        if let Err(e) = manager.remove(guild_id).await {
-            ctx.say(format!("Failed: {:?}", e)).await?;
+    if export.unwrap_or(false) {
+        let csv =
+            tokio::task::spawn_blocking(move || lastheard.export_csv(guild_id.get())).await??;
+        reply = reply.attachment(serenity::CreateAttachment::bytes(csv, "lastheard.csv"));
+    }
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+/// Longest value of an embed field Discord accepts, in characters
+const EMBED_FIELD_MAX: usize = 1024;
+
+/// Show the health of the bridge in this guild
+#[poise::command(slash_command, guild_only)]
+pub async fn status(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("No guild?")?;
+    let uptime =
+        |x: Instant| humantime::format_duration(Duration::from_secs(x.elapsed().as_secs()));
+    let client = ctx
+        .data()
+        .bridges
+        .lock()
+        .await
+        .get(&guild_id.get())
+        .map(|x| x.client.clone());
+    let Some(client) = client else {
+        ctx.say(format!(
+            "Not bridged in this guild\nBot uptime: {}",
+            uptime(ctx.data().started)
+        ))
+        .await?;
+        return Ok(());
+    };
+    let stats = client.stats();
+
+    let last_rx = match stats.last_rx() {
+        Some(x) => format!(
+            "{} ago from {}",
+            humantime::format_duration(Duration::from_millis(x.at.elapsed().as_millis() as u64)),
+            x.source
+        ),
+        None => "never".to_string(),
+    };
+    let (fill, capacity) = stats.buffer_fill();
+    let (resample_time, resample_count) = stats.resample_time();
+    let users = stats.users();
+    // Discord rejects the whole embed when a field is over the limit
+    let mut users_field = String::new();
+    for (i, (ssrc, user)) in users.iter().enumerate() {
+        let line = format!("{} → {}\n", ssrc, user);
+        let more = format!("… and {} more", users.len() - i);
+        if users_field.chars().count() + line.chars().count() + more.chars().count()
+            > EMBED_FIELD_MAX
+        {
+            users_field.push_str(&more);
+            break;
         }
-        ctx.say(&format!("Left {}", channel_id.mention())).await?;
-    } else {
-        ctx.reply("⚠️ Not in a voice channel").await?;
+        users_field.push_str(&line);
     }
 
+    let embed = serenity::CreateEmbed::new()
+        .title("Bridge status")
+        .field(
+            format!("{} peer", ctx.data().config.backend),
+            client.peer(),
+            true,
+        )
+        .field("Last RF packet", last_rx, true)
+        .field(
+            "Packets",
+            format!(
+                "{} in, {} out, {} rejected",
+                stats.packets_in(),
+                stats.packets_out(),
+                stats.packets_rejected()
+            ),
+            true,
+        )
+        .field(
+            "Traffic",
+            format!("{} B in, {} B out", stats.bytes_in(), stats.bytes_out()),
+            true,
+        )
+        .field(
+            "Audio",
+            format!(
+                "{:.0}s from RF, {:.0}s to RF",
+                stats.samples_in() as f64 / client.sample_rate() as f64,
+                stats.samples_out() as f64 / client.sample_rate() as f64
+            ),
+            true,
+        )
+        .field("Sequence gaps", stats.sequence_gaps().to_string(), true)
+        .field(
+            "Resampling",
+            format!(
+                "{}µs per chunk",
+                resample_time.as_micros() / resample_count.max(1) as u128
+            ),
+            true,
+        )
+        .field(
+            "Buffer",
+            format!(
+                "{} / {} bytes ({}%)",
+                fill,
+                capacity,
+                fill * 100 / capacity.max(1)
+            ),
+            true,
+        )
+        .field(
+            "Talking",
+            stats.talker().unwrap_or_else(|| "nobody".to_string()),
+            true,
+        )
+        .field(
+            "SSRC → user",
+            if users.is_empty() {
+                "none".to_string()
+            } else {
+                users_field.trim_end().to_string()
+            },
+            false,
+        )
+        .field("Bridge uptime", uptime(stats.started).to_string(), true)
+        .field("Bot uptime", uptime(ctx.data().started).to_string(), true);
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
     Ok(())
 }
 
@@ -0,0 +1,311 @@
+use discord_bridge::{audio::ChannelMode, resample::ResamplerQuality};
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use std::{env, fmt::Debug, net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
+
+use crate::{
+    backend::Backend,
+    logging::{LogFormat, LogLevels, LogRotation},
+    usrp::SAMPLE_RATES,
+};
+
+/// Parse an optional environment variable, panicking on malformed values
+fn env_opt<T: FromStr>(key: &str) -> Option<T>
+where
+    T::Err: Debug,
+{
+    env::var(key).ok().map(|x| {
+        x.parse()
+            .unwrap_or_else(|e| panic!("Invalid {}: {:?}", key, e))
+    })
+}
+
+fn env_or<T: FromStr>(key: &str, default: T) -> T
+where
+    T::Err: Debug,
+{
+    env_opt(key).unwrap_or(default)
+}
+
+/// Settings used when a bridge is created with `/join`
+#[derive(Clone, Debug)]
+pub struct BridgeConfig {
+    /// What the Discord channel is bridged with
+    pub backend: Backend,
+
+    /// Address the USRP peer sends audio to, bound locally
+    pub local_rx_addr: SocketAddr,
+    /// Address of the USRP peer we send audio to
+    pub target_rx_addr: SocketAddr,
+    /// USRP audio sample rate, 8000 (slin) or 16000 (slin16), unless a guild
+    /// picked its own with `/join`
+    pub usrp_sample_rate: usize,
+
+    /// `host` or `host:port` of the Mumble server
+    #[cfg(feature = "mumble")]
+    pub mumble_server: String,
+    #[cfg(feature = "mumble")]
+    pub mumble_username: String,
+    #[cfg(feature = "mumble")]
+    pub mumble_password: Option<String>,
+    /// Channel joined on the Mumble server, the root channel when None
+    #[cfg(feature = "mumble")]
+    pub mumble_channel: Option<String>,
+    /// Accept self-signed server certificates
+    #[cfg(feature = "mumble")]
+    pub mumble_accept_invalid_certs: bool,
+
+    pub resampler_quality: ResamplerQuality,
+    /// How stereo Discord audio is folded into mono for RF
+    pub channel_mode: ChannelMode,
+    /// Give every RF source its own position in the Discord stereo field
+    pub rf_panning: bool,
+
+    /// Only members with a callsign or the licensed role may transmit to RF
+    pub require_license: bool,
+    pub licensed_role: Option<RoleId>,
+
+    /// Only pass Discord audio to RF while a control operator is in the channel
+    pub require_control_op: bool,
+    pub control_op_role: Option<RoleId>,
+    /// Played in the voice channel when the last control operator leaves
+    pub control_op_announcement: Option<PathBuf>,
+
+    /// RadioID style `user.csv` dump used to resolve DMR IDs
+    pub radioid_csv: Option<PathBuf>,
+    /// JSON file backing the `/callsign` registry
+    pub callsign_registry: PathBuf,
+    /// Users allowed to verify, lock and change other users' callsigns
+    pub registry_admins: Vec<UserId>,
+    /// Voice channels joined on startup
+    pub auto_join: Vec<(GuildId, ChannelId)>,
+    /// Join a channel of `auto_join` again when someone enters it
+    pub auto_join_on_enter: bool,
+    /// Leave once the voice channel has been without humans for this long
+    pub auto_leave: Option<Duration>,
+    /// JSON lines file every transmission is logged to
+    pub lastheard_log: PathBuf,
+
+    /// Show who is talking on the voice channel status
+    pub status_channel: bool,
+    /// Show who is talking on the bot's activity
+    pub status_presence: bool,
+    /// Minimum time between two status updates
+    pub status_interval: Duration,
+    /// Longest time spent tearing the bridges down on SIGTERM or SIGINT
+    pub shutdown_timeout: Duration,
+
+    pub log_format: LogFormat,
+    pub log_levels: LogLevels,
+    /// None logs to stdout only
+    pub log_file: Option<PathBuf>,
+    pub log_rotation: LogRotation,
+    /// Also log to the local syslog daemon
+    #[cfg(feature = "syslog")]
+    pub log_syslog: bool,
+
+    /// Address the Prometheus `/metrics` endpoint listens on
+    #[cfg(feature = "metrics")]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Address the web dashboard listens on
+    #[cfg(feature = "dashboard")]
+    pub dashboard_addr: Option<SocketAddr>,
+
+    /// Address the listen stream is served on
+    #[cfg(feature = "listen")]
+    pub listen_addr: Option<SocketAddr>,
+
+    /// Icecast mount every bridge streams to, `{guild_id}` is replaced
+    #[cfg(feature = "icecast")]
+    pub icecast_url: Option<String>,
+    /// Stream name announced to the Icecast server
+    #[cfg(feature = "icecast")]
+    pub icecast_name: Option<String>,
+
+    /// Address the admin API listens on
+    #[cfg(feature = "api")]
+    pub api_addr: Option<SocketAddr>,
+    /// Bearer token required by the admin API, which does not start without it
+    #[cfg(feature = "api")]
+    pub api_token: Option<String>,
+    /// Directory the announce endpoint plays files from, announcements are off when None
+    #[cfg(feature = "api")]
+    pub announce_dir: Option<PathBuf>,
+    /// A bridge without USRP traffic for this long is reported unhealthy, zero disables
+    #[cfg(feature = "api")]
+    pub health_rx_timeout: Duration,
+}
+
+impl BridgeConfig {
+    pub fn from_env() -> Self {
+        let backend = env_or("BACKEND", Backend::default());
+        // Only the USRP backend uses the addresses, the others may leave them out
+        let unused = (backend != Backend::Usrp).then(|| SocketAddr::from(([0, 0, 0, 0], 0)));
+        let target_rx_addr = env_opt("TARGET_RX_ADDR")
+            .or(unused)
+            .expect("Expected a target rx address in the environment");
+        let local_rx_addr = env_opt("LOCAL_RX_ADDR")
+            .or(unused)
+            .expect("Expected a local rx address in the environment");
+        let usrp_sample_rate = env_or("USRP_SAMPLE_RATE", 8000);
+        assert!(
+            SAMPLE_RATES.contains(&usrp_sample_rate),
+            "Invalid USRP_SAMPLE_RATE: {}, expected 8000 or 16000",
+            usrp_sample_rate
+        );
+        #[cfg(feature = "mumble")]
+        let mumble_server = env_or("MUMBLE_SERVER", "localhost".to_string());
+        #[cfg(feature = "mumble")]
+        let mumble_username = env_or("MUMBLE_USERNAME", "discord-bridge".to_string());
+        #[cfg(feature = "mumble")]
+        let mumble_password = env_opt("MUMBLE_PASSWORD");
+        #[cfg(feature = "mumble")]
+        let mumble_channel = env_opt("MUMBLE_CHANNEL");
+        #[cfg(feature = "mumble")]
+        let mumble_accept_invalid_certs = env_or("MUMBLE_ACCEPT_INVALID_CERTS", false);
+        let resampler_quality = env_or("RESAMPLER_QUALITY", ResamplerQuality::default());
+        let channel_mode = env_or("CHANNEL_MODE", ChannelMode::default());
+        let rf_panning = env_or("RF_PANNING", false);
+        let require_license = env_or("REQUIRE_LICENSE", false);
+        let licensed_role = env_opt("LICENSED_ROLE_ID").map(RoleId::new);
+        let require_control_op = env_or("REQUIRE_CONTROL_OP", false);
+        let control_op_role = env_opt("CONTROL_OP_ROLE_ID").map(RoleId::new);
+        let control_op_announcement = env_opt("CONTROL_OP_ANNOUNCEMENT");
+        let radioid_csv = env_opt("RADIOID_CSV");
+        let auto_join = env::var("AUTO_JOIN")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(|x| {
+                x.split_once(':')
+                    .and_then(|(guild, channel)| Some((guild.parse().ok()?, channel.parse().ok()?)))
+                    .map(|(guild, channel)| (GuildId::new(guild), ChannelId::new(channel)))
+                    .unwrap_or_else(|| {
+                        panic!("Invalid AUTO_JOIN entry: {}, expected guild:channel", x)
+                    })
+            })
+            .collect();
+        let auto_join_on_enter = env_or("AUTO_JOIN_ON_ENTER", false);
+        let auto_leave = env_opt("AUTO_LEAVE_SECS").map(Duration::from_secs);
+        let callsign_registry = env_or("CALLSIGN_REGISTRY", PathBuf::from("callsigns.json"));
+        let registry_admins = env::var("REGISTRY_ADMINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(|x| {
+                x.parse()
+                    .map(UserId::new)
+                    .unwrap_or_else(|_| panic!("Invalid REGISTRY_ADMINS entry: {}", x))
+            })
+            .collect();
+        let lastheard_log = env_or("LASTHEARD_LOG", PathBuf::from("lastheard.jsonl"));
+        let status_channel = env_or("STATUS_CHANNEL", true);
+        let status_presence = env_or("STATUS_PRESENCE", true);
+        let status_interval = Duration::from_millis(env_or("STATUS_INTERVAL_MS", 2000));
+        let shutdown_timeout = Duration::from_secs(env_or("SHUTDOWN_TIMEOUT_SECS", 10));
+        let log_format = env_or("LOG_FORMAT", LogFormat::default());
+        let log_levels = env_or("LOG_LEVEL", LogLevels::default());
+        let log_file = Some(env_or("LOG_FILE", PathBuf::from("output.log")))
+            .filter(|x| !x.as_os_str().is_empty());
+        let log_rotation = env_or("LOG_ROTATION", LogRotation::default());
+        #[cfg(feature = "syslog")]
+        let log_syslog = env_or("LOG_SYSLOG", false);
+        #[cfg(all(not(unix), feature = "syslog"))]
+        assert!(!log_syslog, "LOG_SYSLOG is only supported on unix");
+        #[cfg(feature = "metrics")]
+        let metrics_addr = env_opt("METRICS_ADDR");
+        #[cfg(feature = "dashboard")]
+        let dashboard_addr = env_opt("DASHBOARD_ADDR");
+        #[cfg(feature = "listen")]
+        let listen_addr = env_opt("LISTEN_ADDR");
+        #[cfg(feature = "icecast")]
+        let icecast_url: Option<String> = env_opt("ICECAST_URL");
+        #[cfg(feature = "icecast")]
+        if let Some(url) = &icecast_url {
+            if let Err(e) = url::Url::parse(&url.replace("{guild_id}", "0")) {
+                panic!("Invalid ICECAST_URL: {:?}", e);
+            }
+        }
+        #[cfg(feature = "icecast")]
+        let icecast_name = env_opt("ICECAST_NAME");
+        #[cfg(feature = "api")]
+        let api_addr: Option<SocketAddr> = env_opt("API_ADDR");
+        #[cfg(feature = "api")]
+        let api_token = env_opt("API_TOKEN").filter(|x: &String| !x.is_empty());
+        #[cfg(feature = "api")]
+        assert!(
+            api_addr.is_none() || api_token.is_some(),
+            "API_ADDR needs API_TOKEN"
+        );
+        #[cfg(feature = "api")]
+        let announce_dir = env_opt("ANNOUNCE_DIR");
+        #[cfg(feature = "api")]
+        let health_rx_timeout = Duration::from_secs(env_or("HEALTH_RX_TIMEOUT_SECS", 300));
+        assert!(
+            !require_control_op || control_op_role.is_some(),
+            "REQUIRE_CONTROL_OP needs CONTROL_OP_ROLE_ID"
+        );
+
+        Self {
+            backend,
+            local_rx_addr,
+            target_rx_addr,
+            usrp_sample_rate,
+            #[cfg(feature = "mumble")]
+            mumble_server,
+            #[cfg(feature = "mumble")]
+            mumble_username,
+            #[cfg(feature = "mumble")]
+            mumble_password,
+            #[cfg(feature = "mumble")]
+            mumble_channel,
+            #[cfg(feature = "mumble")]
+            mumble_accept_invalid_certs,
+            resampler_quality,
+            channel_mode,
+            rf_panning,
+            require_license,
+            licensed_role,
+            require_control_op,
+            control_op_role,
+            control_op_announcement,
+            radioid_csv,
+            callsign_registry,
+            registry_admins,
+            auto_join,
+            auto_join_on_enter,
+            auto_leave,
+            lastheard_log,
+            status_channel,
+            status_presence,
+            status_interval,
+            shutdown_timeout,
+            log_format,
+            log_levels,
+            log_file,
+            log_rotation,
+            #[cfg(feature = "syslog")]
+            log_syslog,
+            #[cfg(feature = "metrics")]
+            metrics_addr,
+            #[cfg(feature = "dashboard")]
+            dashboard_addr,
+            #[cfg(feature = "listen")]
+            listen_addr,
+            #[cfg(feature = "icecast")]
+            icecast_url,
+            #[cfg(feature = "icecast")]
+            icecast_name,
+            #[cfg(feature = "api")]
+            api_addr,
+            #[cfg(feature = "api")]
+            api_token,
+            #[cfg(feature = "api")]
+            announce_dir,
+            #[cfg(feature = "api")]
+            health_rx_timeout,
+        }
+    }
+}
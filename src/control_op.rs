@@ -0,0 +1,94 @@
+use crate::{session, Data};
+use log::{info, warn};
+use poise::serenity_prelude as serenity;
+use serenity::{
+    all::{GuildId, VoiceState},
+    client::Context,
+};
+
+/// Record the control operator status of a member from a voice state update
+pub async fn track_voice_state(data: &Data, voice_state: &VoiceState) {
+    let (Some(role), Some(guild_id), Some(member)) = (
+        data.config.control_op_role,
+        voice_state.guild_id,
+        voice_state.member.as_ref(),
+    ) else {
+        return;
+    };
+    data.guild_settings(guild_id)
+        .await
+        .set_control_op(member.user.id, member.roles.contains(&role));
+}
+
+/// Recompute whether a control operator is in the bot's voice channel.
+///
+/// The announcement is played when the last control operator leaves, or
+/// when `announce_if_absent` is set and nobody holds the role.
+pub async fn update_presence(
+    ctx: &Context,
+    data: &Data,
+    guild_id: GuildId,
+    announce_if_absent: bool,
+) {
+    let Some(role) = data.config.control_op_role else {
+        return;
+    };
+    let settings = data.guild_settings(guild_id).await;
+
+    let present = {
+        let Some(guild) = guild_id.to_guild_cached(&ctx.cache) else {
+            return;
+        };
+        let bot_id = ctx.cache.current_user().id;
+        let Some(channel_id) = guild
+            .voice_states
+            .get(&bot_id)
+            .and_then(|voice_state| voice_state.channel_id)
+        else {
+            return;
+        };
+
+        guild
+            .voice_states
+            .values()
+            .filter(|x| x.channel_id == Some(channel_id) && x.user_id != bot_id)
+            .any(|x| {
+                // Members not seen in a voice state update yet fall back to the cache
+                settings.is_control_op(x.user_id).unwrap_or_else(|| {
+                    guild
+                        .members
+                        .get(&x.user_id)
+                        .is_some_and(|member| member.roles.contains(&role))
+                })
+            })
+    };
+
+    let was_present = settings.set_control_op_present(present);
+    if present && !was_present {
+        info!(
+            "Control operator present in guild {}, RF transmit enabled",
+            guild_id
+        );
+    } else if !present && (was_present || announce_if_absent) {
+        info!(
+            "No control operator in guild {}, RF transmit disabled",
+            guild_id
+        );
+        if data.config.require_control_op {
+            announce(ctx, data, guild_id).await;
+        }
+    }
+}
+
+/// Play the "no control operator" announcement in the voice channel
+async fn announce(ctx: &Context, data: &Data, guild_id: GuildId) {
+    let Some(path) = data.config.control_op_announcement.clone() else {
+        return;
+    };
+    if let Err(e) = session::announce(ctx, guild_id, path).await {
+        warn!(
+            "Failed to play the announcement in guild {}: {}",
+            guild_id, e
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use axum::{
+    extract::State,
+    http::header,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse,
+    },
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use discord_bridge::lastheard::{Direction, Transmission};
+use log::{info, warn};
+use poise::serenity_prelude as serenity;
+use serde::Serialize;
+use serenity::all::{Context, GuildId};
+use std::{convert::Infallible, net::SocketAddr, time::Duration};
+use tokio::sync::watch;
+use tokio_stream::{wrappers::WatchStream, Stream, StreamExt};
+
+use crate::Data;
+
+/// How often the state is refreshed, also the resolution of the level meters
+const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+/// Transmissions shown per bridge
+const LASTHEARD_COUNT: usize = 10;
+
+#[derive(Serialize)]
+struct BridgeView {
+    guild_id: u64,
+    guild_name: Option<String>,
+    channel_id: Option<u64>,
+    channel_name: Option<String>,
+    /// Empty when the RF talker did not identify
+    rf_talker: Option<String>,
+    discord_talker: Option<String>,
+    /// Peak levels over the last update interval, in percent
+    level_to_rf: u8,
+    level_from_rf: u8,
+    /// Only the number of users, the page is not meant to tell who is listening
+    discord_users: usize,
+    uptime_secs: u64,
+    lastheard: Vec<TransmissionView>,
+}
+
+/// What the page shows of a transmission, without the Discord user or RF peer
+#[derive(Serialize)]
+struct TransmissionView {
+    start: DateTime<Utc>,
+    direction: Direction,
+    callsign: String,
+    duration_secs: f64,
+}
+
+impl From<Transmission> for TransmissionView {
+    fn from(transmission: Transmission) -> Self {
+        Self {
+            start: transmission.start,
+            direction: transmission.direction,
+            duration_secs: transmission.duration().num_milliseconds() as f64 / 1000.0,
+            callsign: transmission.callsign,
+        }
+    }
+}
+
+/// Serve the dashboard until the process exits
+pub async fn serve(addr: SocketAddr, ctx: Context, data: Data) {
+    let (sender, receiver) = watch::channel(String::new());
+    tokio::spawn(update(ctx, data, sender));
+
+    let app = Router::new()
+        .route("/", get(|| async { Html(include_str!("dashboard.html")) }))
+        .route("/state", get(state))
+        .route("/events", get(events))
+        .with_state(receiver);
+    let listener = match tokio::net::TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Failed to bind dashboard {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("Serving dashboard on http://{}", addr);
+    if let Err(e) = axum::serve(listener, app).await {
+        warn!("Dashboard failed: {}", e);
+    }
+}
+
+/// Snapshot every bridge, publishing the JSON when something changed
+async fn update(ctx: Context, data: Data, sender: watch::Sender<String>) {
+    let mut interval = tokio::time::interval(UPDATE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let bridges = snapshot(&ctx, &data).await;
+        let json = serde_json::to_string(&bridges).unwrap_or_default();
+        sender.send_if_modified(|x| {
+            let modified = *x != json;
+            *x = json;
+            modified
+        });
+    }
+}
+
+async fn snapshot(ctx: &Context, data: &Data) -> Vec<BridgeView> {
+    let clients: Vec<_> = data
+        .bridges
+        .lock()
+        .await
+        .iter()
+        .map(|(&guild_id, bridge)| (guild_id, bridge.client.clone()))
+        .collect();
+    let manager = songbird::get(ctx).await;
+
+    let mut bridges = Vec::new();
+    for (guild_id, client) in clients {
+        let call = manager.as_ref().and_then(|x| x.get(GuildId::new(guild_id)));
+        let channel_id = match call {
+            Some(call) => call.lock().await.current_channel().map(|x| x.0.get()),
+            None => None,
+        };
+        let guild = GuildId::new(guild_id).to_guild_cached(&ctx.cache);
+        let channel_name = guild.as_ref().and_then(|guild| {
+            let channel_id = channel_id?;
+            guild
+                .channels
+                .values()
+                .find(|x| x.id.get() == channel_id)
+                .map(|x| x.name.clone())
+        });
+
+        let stats = client.stats();
+        let talking = stats.now_talking();
+        let (to_rf, from_rf) = stats.take_levels();
+        bridges.push(BridgeView {
+            guild_id,
+            guild_name: guild.map(|x| x.name.clone()),
+            channel_id,
+            channel_name,
+            rf_talker: talking.rf,
+            discord_talker: talking.discord,
+            level_to_rf: (to_rf * 100.0).round() as u8,
+            level_from_rf: (from_rf * 100.0).round() as u8,
+            discord_users: stats.users().len(),
+            uptime_secs: stats.started.elapsed().as_secs(),
+            lastheard: data
+                .lastheard
+                .recent(guild_id, LASTHEARD_COUNT)
+                .into_iter()
+                .map(TransmissionView::from)
+                .collect(),
+        });
+    }
+    bridges.sort_by_key(|x| x.guild_id);
+    bridges
+}
+
+async fn state(State(receiver): State<watch::Receiver<String>>) -> impl IntoResponse {
+    let json = receiver.borrow().clone();
+    ([(header::CONTENT_TYPE, "application/json")], json)
+}
+
+/// The state of every bridge, pushed whenever it changes
+async fn events(
+    State(receiver): State<watch::Receiver<String>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = WatchStream::new(receiver).map(|json| Ok(Event::default().data(json)));
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
@@ -1,13 +1,32 @@
+use poise::serenity_prelude as serenity;
 use serenity::{
     async_trait,
     client::{Context, EventHandler},
     model::gateway::Ready,
+    FullEvent,
 };
 
+use crate::{control_op, session, Data, Error};
+
 pub struct Handler;
 
 #[async_trait]
 impl EventHandler for Handler {
-    async fn ready(&self, _: Context, _ready: Ready) {
+    async fn ready(&self, _: Context, _ready: Ready) {}
+}
+
+/// Gateway events handled through the framework, with access to the bot data
+pub async fn event_handler(ctx: &Context, event: &FullEvent, data: &Data) -> Result<(), Error> {
+    if let FullEvent::VoiceStateUpdate { new, .. } = event {
+        control_op::track_voice_state(data, new).await;
+        if let Some(member) = &new.member {
+            session::refresh_member(data, member).await;
+        }
+        if let Some(guild_id) = new.guild_id {
+            control_op::update_presence(ctx, data, guild_id, false).await;
+            session::check_idle(ctx, data, guild_id).await;
+        }
+        session::join_on_enter(ctx, data, new).await;
     }
+    Ok(())
 }
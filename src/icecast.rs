@@ -0,0 +1,181 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use discord_bridge::ogg::OggOpusWriter;
+use log::{info, warn};
+use std::{io, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    sync::broadcast::error::RecvError,
+    time::timeout,
+};
+use tokio_util::sync::CancellationToken;
+use url::Url;
+
+use crate::{
+    listen::{ListenMixer, FRAME_SAMPLES_48K, SAMPLE_RATE},
+    stats::{BridgeStats, NowTalking},
+};
+
+/// Time allowed to connect to the server, and for it to answer the request
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// A server that stops reading for this long is considered gone
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// The source mount of a bridge, `{guild_id}` in the URL is replaced so
+/// every bridge can have its own mount
+pub fn mount_url(url: &str, guild_id: u64) -> Url {
+    Url::parse(&url.replace("{guild_id}", &guild_id.to_string()))
+        .expect("ICECAST_URL is checked when the config is loaded")
+}
+
+/// Feed the bridge audio to an Icecast mount as a source client until
+/// cancelled, reconnecting whenever the connection is lost
+pub async fn run(
+    url: Url,
+    name: Option<String>,
+    mixer: Arc<ListenMixer>,
+    stats: Arc<BridgeStats>,
+    cancel: CancellationToken,
+) {
+    let mount = format!("{}{}", url.host_str().unwrap_or_default(), url.path());
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        let result = tokio::select! {
+            result = source(&url, name.as_deref(), &mixer, &stats, &mut backoff) => result,
+            _ = cancel.cancelled() => return,
+        };
+        if let Err(e) = result {
+            warn!(
+                "Icecast source {} failed: {}, reconnecting in {:?}",
+                mount, e, backoff
+            );
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = cancel.cancelled() => return,
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Connect and stream until the connection fails
+async fn source(
+    url: &Url,
+    name: Option<&str>,
+    mixer: &ListenMixer,
+    stats: &BridgeStats,
+    backoff: &mut Duration,
+) -> io::Result<()> {
+    let mut stream = connect(url, name).await?;
+    info!(
+        "Streaming to Icecast mount {}{}",
+        url.host_str().unwrap_or_default(),
+        url.path()
+    );
+    *backoff = MIN_BACKOFF;
+
+    let mut packets = mixer.subscribe();
+    let sample_rate = SAMPLE_RATE as u32;
+    let mut writer = OggOpusWriter::new(rand::random());
+    let mut title = talker_title(&stats.now_talking());
+    let headers = writer.headers(1, sample_rate, &comments(title.as_deref()));
+    write(&mut stream, &headers).await?;
+
+    loop {
+        let packet = match packets.recv().await {
+            Ok(packet) => packet,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return Ok(()),
+        };
+        let mut pages = Vec::new();
+        // Every transmission starts a new chained stream carrying its talker,
+        // the title stays on the last talker between transmissions
+        if let Some(talker) = talker_title(&stats.now_talking()) {
+            if title.as_ref() != Some(&talker) {
+                pages = writer.chain(rand::random());
+                pages.extend(writer.headers(1, sample_rate, &comments(Some(&talker))));
+                title = Some(talker);
+            }
+        }
+        pages.extend(writer.packet(&packet, FRAME_SAMPLES_48K));
+        write(&mut stream, &pages).await?;
+    }
+}
+
+/// Send the source request, Icecast 2.4 and later accept `PUT` with no body length
+async fn connect(url: &Url, name: Option<&str>) -> io::Result<TcpStream> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No host in ICECAST_URL"))?;
+    let port = url.port_or_known_default().unwrap_or(8000);
+    let mut stream = timeout(CONNECT_TIMEOUT, TcpStream::connect((host, port)))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Connection timed out"))??;
+
+    let user = match url.username() {
+        "" => "source",
+        user => user,
+    };
+    let credentials = STANDARD.encode(format!("{}:{}", user, url.password().unwrap_or_default()));
+    let mut request = format!(
+        "PUT {} HTTP/1.1\r\n\
+         Host: {}:{}\r\n\
+         Authorization: Basic {}\r\n\
+         User-Agent: discord-bridge/{}\r\n\
+         Content-Type: audio/ogg\r\n\
+         Ice-Public: 0\r\n",
+        url.path(),
+        host,
+        port,
+        credentials,
+        env!("CARGO_PKG_VERSION")
+    );
+    if let Some(name) = name {
+        request += &format!("Ice-Name: {}\r\n", name);
+    }
+    request += "\r\n";
+    stream.write_all(request.as_bytes()).await?;
+
+    let status = timeout(CONNECT_TIMEOUT, async {
+        let mut reader = BufReader::new(&mut stream);
+        let mut status = String::new();
+        reader.read_line(&mut status).await?;
+        // Nothing follows the response headers, skip them
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+                break;
+            }
+        }
+        Ok::<_, io::Error>(status)
+    })
+    .await
+    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "No response to the source request"))??;
+
+    match status.split_whitespace().nth(1) {
+        Some("200") => Ok(stream),
+        _ => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("Source refused: {}", status.trim()),
+        )),
+    }
+}
+
+async fn write(stream: &mut TcpStream, data: &[u8]) -> io::Result<()> {
+    timeout(WRITE_TIMEOUT, stream.write_all(data))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Write timed out"))?
+}
+
+/// Metadata of the current talker, None when nobody talks
+fn talker_title(talking: &NowTalking) -> Option<String> {
+    talking
+        .current()
+        .map(|(_, talker)| if talker.is_empty() { "RF" } else { talker }.to_string())
+}
+
+fn comments(title: Option<&str>) -> Vec<String> {
+    title.map(|x| format!("TITLE={}", x)).into_iter().collect()
+}
@@ -0,0 +1,245 @@
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+/// Number of transmissions kept in memory for `/lastheard`
+const RECENT_CAPACITY: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// From Discord to RF
+    ToRf,
+    /// From RF to Discord
+    FromRf,
+}
+
+impl Direction {
+    /// Name used in structured log fields, same as the serialized one
+    pub fn key(&self) -> &'static str {
+        match self {
+            Direction::ToRf => "to_rf",
+            Direction::FromRf => "from_rf",
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::ToRf => write!(f, "Discord → RF"),
+            Direction::FromRf => write!(f, "RF → Discord"),
+        }
+    }
+}
+
+/// A single transmission through the bridge
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Transmission {
+    pub guild_id: u64,
+    pub direction: Direction,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub callsign: String,
+    /// Discord user, for transmissions to RF
+    pub user_id: Option<u64>,
+    pub user_name: Option<String>,
+    /// RF metadata from the USRP start packet
+    pub dmr_id: Option<u32>,
+    pub talkgroup: Option<u32>,
+    /// USRP peer, for transmissions from RF
+    pub rf_source: Option<SocketAddr>,
+}
+
+impl Transmission {
+    /// A transmission starting now
+    pub fn start(guild_id: u64, direction: Direction) -> Self {
+        let now = Utc::now();
+        Self {
+            guild_id,
+            direction,
+            start: now,
+            end: now,
+            callsign: String::new(),
+            user_id: None,
+            user_name: None,
+            dmr_id: None,
+            talkgroup: None,
+            rf_source: None,
+        }
+    }
+
+    pub fn duration(&self) -> chrono::Duration {
+        self.end - self.start
+    }
+}
+
+/// Work for the thread appending to the log file
+enum WriterMessage {
+    Record(Box<Transmission>),
+    /// Answered once everything sent before is written
+    Flush(mpsc::Sender<()>),
+}
+
+/// Persistent log of every transmission, stored as JSON lines
+pub struct LastHeard {
+    path: PathBuf,
+    recent: Mutex<VecDeque<Transmission>>,
+    /// Transmissions are written by a thread of their own, so recording one
+    /// never blocks the audio path on file I/O
+    writer: mpsc::Sender<WriterMessage>,
+}
+
+impl LastHeard {
+    /// Load the most recent transmissions, a missing file is an empty log
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut recent = VecDeque::with_capacity(RECENT_CAPACITY);
+        for transmission in read_log(&path)? {
+            if recent.len() == RECENT_CAPACITY {
+                recent.pop_front();
+            }
+            recent.push_back(transmission);
+        }
+        let (writer, messages) = mpsc::channel();
+        let writer_path = path.clone();
+        thread::Builder::new()
+            .name("lastheard".to_string())
+            .spawn(move || write_log(&writer_path, messages))?;
+        Ok(Self {
+            path,
+            recent: Mutex::new(recent),
+            writer,
+        })
+    }
+
+    /// Log a finished transmission, it is appended to the file in the background
+    pub fn record(&self, transmission: Transmission) {
+        info!(
+            bridge = transmission.guild_id,
+            direction = transmission.direction.key(),
+            callsign = transmission.callsign.as_str(),
+            user_id = transmission.user_id,
+            dmr_id = transmission.dmr_id,
+            duration_ms = transmission.duration().num_milliseconds();
+            "{} transmission by {} ended after {:.1}s",
+            transmission.direction,
+            transmission.callsign,
+            transmission.duration().num_milliseconds() as f64 / 1000.0
+        );
+        let _ = self
+            .writer
+            .send(WriterMessage::Record(Box::new(transmission.clone())));
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == RECENT_CAPACITY {
+            recent.pop_front();
+        }
+        recent.push_back(transmission);
+    }
+
+    /// Wait until every recorded transmission is in the file, this blocks
+    pub fn flush(&self) {
+        let (done, flushed) = mpsc::channel();
+        if self.writer.send(WriterMessage::Flush(done)).is_ok() {
+            let _ = flushed.recv();
+        }
+    }
+
+    /// The `n` most recent transmissions of a guild, newest first
+    pub fn recent(&self, guild_id: u64, n: usize) -> Vec<Transmission> {
+        self.recent
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|x| x.guild_id == guild_id)
+            .take(n)
+            .cloned()
+            .collect()
+    }
+
+    /// The whole log of a guild as CSV, oldest first. This blocks on file I/O
+    pub fn export_csv(&self, guild_id: u64) -> io::Result<Vec<u8>> {
+        self.flush();
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record([
+            "start",
+            "end",
+            "duration_s",
+            "direction",
+            "callsign",
+            "user_id",
+            "user_name",
+            "dmr_id",
+            "talkgroup",
+            "rf_source",
+        ])?;
+        let opt = |x: Option<String>| x.unwrap_or_default();
+        for x in read_log(&self.path)?.filter(|x| x.guild_id == guild_id) {
+            writer.write_record([
+                x.start.to_rfc3339(),
+                x.end.to_rfc3339(),
+                format!("{:.1}", x.duration().num_milliseconds() as f64 / 1000.0),
+                format!("{:?}", x.direction),
+                x.callsign.clone(),
+                opt(x.user_id.map(|x| x.to_string())),
+                opt(x.user_name.clone()),
+                opt(x.dmr_id.map(|x| x.to_string())),
+                opt(x.talkgroup.map(|x| x.to_string())),
+                opt(x.rf_source.map(|x| x.to_string())),
+            ])?;
+        }
+        writer.into_inner().map_err(|e| e.into_error())
+    }
+}
+
+/// Append the transmissions received to the log file until the `LastHeard`
+/// is dropped
+fn write_log(path: &Path, messages: mpsc::Receiver<WriterMessage>) {
+    for message in messages {
+        match message {
+            WriterMessage::Record(transmission) => {
+                let written = serde_json::to_vec(&transmission)
+                    .map_err(io::Error::from)
+                    .and_then(|mut line| {
+                        line.push(b'\n');
+                        OpenOptions::new()
+                            .create(true)
+                            .append(true)
+                            .open(path)?
+                            .write_all(&line)
+                    });
+                if let Err(e) = written {
+                    warn!("Failed to log transmission: {}", e);
+                }
+            }
+            WriterMessage::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+/// Every transmission in the log file, malformed lines are skipped
+fn read_log(path: &Path) -> io::Result<impl Iterator<Item = Transmission>> {
+    let lines = match fs::File::open(path) {
+        Ok(file) => Some(BufReader::new(file).lines()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    Ok(lines
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok()))
+}
@@ -1,6 +1,21 @@
+pub mod audio;
+pub mod callsign;
+pub mod lastheard;
+#[cfg(feature = "mumble")]
+pub mod mumble_proto;
+#[cfg(feature = "mumble")]
+pub mod mumble_voice;
+pub mod ogg;
+pub mod radioid;
+pub mod registry;
+pub mod resample;
+#[cfg(unix)]
+pub mod sdnotify;
+pub mod usrp_packets;
+
 #[derive(PartialEq, Debug)]
 pub enum USRPVoicePacketType {
     Start,
     Audio,
     End,
-}
\ No newline at end of file
+}
@@ -0,0 +1,165 @@
+use audiopus::{coder::Encoder, Application, Channels, SampleRate};
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use discord_bridge::{lastheard::Direction, ogg::OggOpusWriter};
+use log::{info, warn};
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{sync::broadcast, time::MissedTickBehavior};
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+use tokio_util::sync::CancellationToken;
+
+use crate::{usrp::FRAME_MS, Data};
+
+/// Audio buffered per direction before the oldest is dropped
+const MAX_BUFFERED_FRAMES: usize = 25;
+/// Encoded packets kept for listeners that fall behind
+const PACKET_BACKLOG: usize = 50;
+/// Sample rate of the mix, the one Discord and the RF mix are at
+pub const SAMPLE_RATE: usize = 48000;
+/// Samples of one frame at 48 kHz, the unit of Ogg granule positions
+pub const FRAME_SAMPLES_48K: u64 = 48 * FRAME_MS as u64;
+
+/// Mixes the audio crossing the bridge in both directions into a single
+/// Opus stream for listeners who are not on Discord
+pub struct ListenMixer {
+    from_rf: Mutex<VecDeque<f32>>,
+    to_rf: Mutex<VecDeque<f32>>,
+    packets: broadcast::Sender<Arc<[u8]>>,
+}
+
+impl Default for ListenMixer {
+    fn default() -> Self {
+        Self {
+            from_rf: Mutex::default(),
+            to_rf: Mutex::default(),
+            packets: broadcast::Sender::new(PACKET_BACKLOG),
+        }
+    }
+}
+
+impl ListenMixer {
+    /// Encoded packets of the mix, encoding only runs while someone subscribes
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<[u8]>> {
+        self.packets.subscribe()
+    }
+
+    /// Queue 48 kHz mono audio, every direction is pushed already mixed
+    pub fn push(&self, direction: Direction, audio: &[f32]) {
+        let mut buffer = match direction {
+            Direction::FromRf => self.from_rf.lock().unwrap(),
+            Direction::ToRf => self.to_rf.lock().unwrap(),
+        };
+        buffer.extend(audio);
+        let max = FRAME_SAMPLES_48K as usize * MAX_BUFFERED_FRAMES;
+        if buffer.len() > max {
+            let excess = buffer.len() - max;
+            buffer.drain(..excess);
+        }
+    }
+
+    /// Take one frame of each direction and add them up, silence fills the gaps
+    fn mix(&self) -> Vec<f32> {
+        let frame_size = FRAME_SAMPLES_48K as usize;
+        let mut frame = vec![0f32; frame_size];
+        for buffer in [&self.from_rf, &self.to_rf] {
+            let mut buffer = buffer.lock().unwrap();
+            let len = buffer.len().min(frame_size);
+            for (x, sample) in frame.iter_mut().zip(buffer.drain(..len)) {
+                *x += sample;
+            }
+        }
+        frame.into_iter().map(|x| x.clamp(-1.0, 1.0)).collect()
+    }
+
+    /// Encode the mix every frame while someone listens, until cancelled
+    pub async fn run(self: Arc<Self>, cancel: CancellationToken) {
+        let encoder = match Encoder::new(SampleRate::Hz48000, Channels::Mono, Application::Audio) {
+            Ok(encoder) => encoder,
+            Err(e) => {
+                warn!("Failed to create the listen stream encoder: {}", e);
+                return;
+            }
+        };
+        let mut interval = tokio::time::interval(Duration::from_millis(FRAME_MS as u64));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut output = [0u8; 4000];
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = cancel.cancelled() => break,
+            }
+            let frame = self.mix();
+            if self.packets.receiver_count() == 0 {
+                continue;
+            }
+            match encoder.encode_float(&frame, &mut output) {
+                Ok(len) => {
+                    let _ = self.packets.send(output[..len].into());
+                }
+                Err(e) => warn!("Failed to encode the listen stream: {}", e),
+            }
+        }
+    }
+}
+
+/// Serve `/listen/{guild_id}` until the process exits
+pub async fn serve(addr: SocketAddr, data: Data) {
+    let app = Router::new()
+        .route("/listen/{guild_id}", get(listen))
+        .with_state(data);
+    let listener = match tokio::net::TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Failed to bind listen stream {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("Serving listen streams on http://{}/listen/", addr);
+    if let Err(e) = axum::serve(listener, app).await {
+        warn!("Listen stream failed: {}", e);
+    }
+}
+
+/// The audio of a bridge as an endless Ogg/Opus stream
+async fn listen(State(data): State<Data>, Path(guild_id): Path<u64>) -> Response {
+    let mixer = data
+        .bridges
+        .lock()
+        .await
+        .get(&guild_id)
+        .map(|x| x.listen.clone());
+    let Some(mixer) = mixer else {
+        return (StatusCode::NOT_FOUND, "Not bridged in this guild").into_response();
+    };
+    info!("Listener joined the stream of guild {}", guild_id);
+
+    let mut writer = OggOpusWriter::new(rand::random());
+    let headers = writer.headers(1, SAMPLE_RATE as u32, &[]);
+    // Listeners that fall behind skip the packets they missed
+    let pages = BroadcastStream::new(mixer.subscribe())
+        .filter_map(move |x| x.ok().map(|x| writer.packet(&x, FRAME_SAMPLES_48K)));
+    let stream = tokio_stream::once(headers)
+        .chain(pages)
+        .map(Ok::<_, Infallible>);
+
+    (
+        [
+            (header::CONTENT_TYPE, "audio/ogg"),
+            (header::CACHE_CONTROL, "no-cache, no-store"),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response()
+}
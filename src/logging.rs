@@ -0,0 +1,211 @@
+use log::{
+    kv::{Error as KvError, Key, Value, VisitSource},
+    LevelFilter, Record,
+};
+use serde_json::{Map, Value as Json};
+use std::{fmt::Write, str::FromStr};
+
+use crate::config::BridgeConfig;
+
+/// How log lines are written to stdout and the log file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// `[time level target] message key=value`
+    #[default]
+    Text,
+    /// One JSON object per line, structured fields as keys
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" | "plain" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unknown log format: {}", s)),
+        }
+    }
+}
+
+/// When the log file is rotated, rotated files get the date appended to their name
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LogRotation {
+    #[default]
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl FromStr for LogRotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "never" | "none" => Ok(Self::Never),
+            "hourly" => Ok(Self::Hourly),
+            "daily" => Ok(Self::Daily),
+            _ => Err(format!("Unknown log rotation: {}", s)),
+        }
+    }
+}
+
+/// A default level and per module overrides, e.g. `warn,discord_bridge=info`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogLevels {
+    pub default: LevelFilter,
+    pub modules: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogLevels {
+    fn default() -> Self {
+        Self {
+            default: LevelFilter::Warn,
+            modules: vec![
+                ("hyper".to_string(), LevelFilter::Info),
+                ("discord_bridge".to_string(), LevelFilter::Info),
+            ],
+        }
+    }
+}
+
+impl FromStr for LogLevels {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let level = |x: &str| {
+            x.trim()
+                .parse::<LevelFilter>()
+                .map_err(|_| format!("Unknown log level: {}", x))
+        };
+        let mut levels = Self {
+            default: LevelFilter::Warn,
+            modules: Vec::new(),
+        };
+        for directive in s.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, x)) => levels.modules.push((module.trim().to_string(), level(x)?)),
+                None => levels.default = level(directive)?,
+            }
+        }
+        Ok(levels)
+    }
+}
+
+/// Collects the structured fields of a record, e.g. `callsign` or `direction`
+struct Fields(Vec<(String, Json)>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), KvError> {
+        let value = if let Some(x) = value.to_u64() {
+            x.into()
+        } else if let Some(x) = value.to_i64() {
+            x.into()
+        } else if let Some(x) = value.to_bool() {
+            x.into()
+        } else if let Some(x) = value.to_f64() {
+            x.into()
+        } else {
+            value.to_string().into()
+        };
+        self.0.push((key.to_string(), value));
+        Ok(())
+    }
+}
+
+fn fields(record: &Record) -> Vec<(String, Json)> {
+    let mut fields = Fields(Vec::new());
+    let _ = record.key_values().visit(&mut fields);
+    fields.0
+}
+
+/// ` key=value` for every structured field
+fn text_fields(record: &Record) -> String {
+    let mut out = String::new();
+    for (key, value) in fields(record) {
+        match value {
+            Json::String(x) => write!(out, " {}={:?}", key, x),
+            x => write!(out, " {}={}", key, x),
+        }
+        .unwrap();
+    }
+    out
+}
+
+fn json_line(message: &std::fmt::Arguments, record: &Record) -> String {
+    let mut line = Map::new();
+    line.insert(
+        "timestamp".to_string(),
+        humantime::format_rfc3339(std::time::SystemTime::now())
+            .to_string()
+            .into(),
+    );
+    line.insert("level".to_string(), record.level().as_str().into());
+    line.insert("target".to_string(), record.target().into());
+    line.insert("message".to_string(), message.to_string().into());
+    for (key, value) in fields(record) {
+        line.entry(key).or_insert(value);
+    }
+    Json::Object(line).to_string()
+}
+
+/// Set up the global logger from the `LOG_*` settings
+pub fn init(config: &BridgeConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let mut dispatch = fern::Dispatch::new().level(config.log_levels.default);
+    for (module, level) in &config.log_levels.modules {
+        dispatch = dispatch.level_for(module.clone(), *level);
+    }
+
+    let mut output = match config.log_format {
+        LogFormat::Text => fern::Dispatch::new().format(|out, message, record| {
+            out.finish(format_args!(
+                "[{} {} {}] {}{}",
+                humantime::format_rfc3339(std::time::SystemTime::now()),
+                record.level(),
+                record.target(),
+                message,
+                text_fields(record)
+            ))
+        }),
+        LogFormat::Json => fern::Dispatch::new().format(|out, message, record| {
+            out.finish(format_args!("{}", json_line(message, record)))
+        }),
+    }
+    .chain(std::io::stdout());
+    if let Some(path) = &config.log_file {
+        output = match config.log_rotation {
+            LogRotation::Never => output.chain(fern::log_file(path)?),
+            LogRotation::Hourly => output.chain(fern::DateBased::new(
+                format!("{}.", path.display()),
+                "%Y-%m-%d-%H",
+            )),
+            LogRotation::Daily => output.chain(fern::DateBased::new(
+                format!("{}.", path.display()),
+                "%Y-%m-%d",
+            )),
+        };
+    }
+    dispatch = dispatch.chain(output);
+
+    // syslog adds its own timestamp and level, journald picks it up as well
+    #[cfg(all(unix, feature = "syslog"))]
+    if config.log_syslog {
+        let formatter = syslog::Formatter3164 {
+            facility: syslog::Facility::LOG_DAEMON,
+            hostname: None,
+            process: env!("CARGO_PKG_NAME").to_string(),
+            pid: std::process::id(),
+        };
+        dispatch = dispatch.chain(
+            fern::Dispatch::new()
+                .format(|out, message, record| {
+                    out.finish(format_args!("{}{}", message, text_fields(record)))
+                })
+                .chain(syslog::unix(formatter)?),
+        );
+    }
+
+    dispatch.apply()?;
+    Ok(())
+}
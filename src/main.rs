@@ -1,28 +1,83 @@
+#[cfg(feature = "api")]
+mod api;
+mod backend;
 mod bridge;
 mod commands;
+mod config;
+mod control_op;
+#[cfg(feature = "dashboard")]
+mod dashboard;
 mod handler;
+#[cfg(feature = "icecast")]
+mod icecast;
+#[cfg(feature = "listen")]
+mod listen;
+mod logging;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "mumble")]
+mod mumble;
+mod session;
+mod settings;
+mod stats;
+mod status;
 mod usrp;
-mod util;
+#[cfg(unix)]
+mod watchdog;
 
+use config::BridgeConfig;
+#[cfg(unix)]
+use discord_bridge::sdnotify::Notifier;
+use discord_bridge::{lastheard::LastHeard, radioid::RadioIdDatabase, registry::CallsignRegistry};
 use dotenv::dotenv;
 use handler::Handler;
-use log::info;
+use log::{info, warn};
 use poise::serenity_prelude as serenity;
-use serenity::{all::GatewayIntents, client::Client};
+use serenity::{
+    all::{GatewayIntents, GuildId, ShardManager},
+    client::Client,
+};
+use session::Bridge;
+use settings::GuildSettings;
 use songbird::{driver::DecodeMode, Config, SerenityInit};
-use usrp::USRPClient;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     env,
-    sync::Arc,
-    time::Duration,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
 };
 use tokio::sync::Mutex;
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
 type Context<'a> = poise::Context<'a, Data, Error>;
+#[derive(Clone)]
 pub struct Data {
-    clients: Mutex<HashMap<u64, Arc<USRPClient>>>,
+    config: BridgeConfig,
+    bridges: Arc<Mutex<HashMap<u64, Bridge>>>,
+    settings: Arc<Mutex<HashMap<u64, Arc<GuildSettings>>>>,
+    radioid: Arc<RwLock<RadioIdDatabase>>,
+    registry: Arc<CallsignRegistry>,
+    lastheard: Arc<LastHeard>,
+    started: Instant,
+    /// Guilds with a join in progress
+    joining: Arc<std::sync::Mutex<HashSet<u64>>>,
+    /// Guilds with a join retried in the background
+    rejoining: Arc<std::sync::Mutex<HashSet<u64>>>,
+    /// systemd notify socket, when run as a `Type=notify` service
+    #[cfg(unix)]
+    notifier: Option<Arc<Notifier>>,
+}
+
+impl Data {
+    /// Settings of a guild, created on first use
+    pub async fn guild_settings(&self, guild_id: GuildId) -> Arc<GuildSettings> {
+        self.settings
+            .lock()
+            .await
+            .entry(guild_id.get())
+            .or_default()
+            .clone()
+    }
 }
 
 async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
@@ -32,50 +87,112 @@ async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
     match error {
         poise::FrameworkError::Setup { error, .. } => panic!("Failed to start bot: {:?}", error),
         poise::FrameworkError::Command { error, ctx, .. } => {
-            println!("Error in command `{}`: {:?}", ctx.command().name, error,);
+            warn!("Error in command `{}`: {:?}", ctx.command().name, error);
         }
         error => {
             if let Err(e) = poise::builtins::on_error(error).await {
-                println!("Error while handling error: {}", e)
+                warn!("Error while handling error: {}", e)
             }
         }
     }
 }
 
+/// Resolves on SIGINT, or SIGTERM on unix
+async fn signal() {
+    let ctrl_c = tokio::signal::ctrl_c();
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigterm = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+        tokio::select! {
+            _ = ctrl_c => {}
+            _ = sigterm.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = ctrl_c.await;
+}
+
+/// Tear the bridges down on a signal, then stop the gateway so `main` returns
+async fn shutdown_on_signal(ctx: serenity::Context, data: Data, shard_manager: Arc<ShardManager>) {
+    signal().await;
+    info!("Shutting down");
+    #[cfg(unix)]
+    if let Some(notifier) = &data.notifier {
+        let _ = notifier.stopping();
+    }
+    let timeout = data.config.shutdown_timeout;
+    if tokio::time::timeout(timeout, session::shutdown(&ctx, &data))
+        .await
+        .is_err()
+    {
+        warn!("Shutdown did not finish within {}s", timeout.as_secs());
+    }
+    log::logger().flush();
+    shard_manager.shutdown_all().await;
+}
+
 #[tokio::main]
 async fn main() {
     dotenv().ok();
 
-    env::var("TARGET_RX_ADDR").expect("Expected a target rx address in the environment");
-    env::var("LOCAL_RX_ADDR").expect("Expected a local rx address in the environment");
-
-    fern::Dispatch::new()
-        // Perform allocation-free log formatting
-        .format(|out, message, record| {
-            out.finish(format_args!(
-                "[{} {} {}] {}",
-                humantime::format_rfc3339(std::time::SystemTime::now()),
-                record.level(),
-                record.target(),
-                message
-            ))
+    let config = BridgeConfig::from_env();
+
+    logging::init(&config).expect("Failed to initialise logging");
+
+    let radioid = match &config.radioid_csv {
+        Some(path) => RadioIdDatabase::load(path).unwrap_or_else(|e| {
+            warn!("Failed to load DMR ID database {}: {}", path.display(), e);
+            RadioIdDatabase::default()
+        }),
+        None => RadioIdDatabase::default(),
+    };
+    info!("Loaded {} DMR IDs", radioid.len());
+
+    let registry = CallsignRegistry::load(&config.callsign_registry).unwrap_or_else(|e| {
+        panic!(
+            "Failed to load callsign registry {}: {}",
+            config.callsign_registry.display(),
+            e
+        )
+    });
+    let lastheard = LastHeard::load(&config.lastheard_log).unwrap_or_else(|e| {
+        panic!(
+            "Failed to load last heard log {}: {}",
+            config.lastheard_log.display(),
+            e
+        )
+    });
+
+    let bridges = Arc::new(Mutex::new(HashMap::new()));
+    #[cfg(feature = "metrics")]
+    let (metrics_addr, metrics_backend, metrics_bridges) =
+        (config.metrics_addr, config.backend, bridges.clone());
+
+    #[cfg(unix)]
+    let notifier = Notifier::from_env()
+        .unwrap_or_else(|e| {
+            warn!("Failed to open the systemd notify socket: {}", e);
+            None
         })
-        // Add blanket level filter -
-        .level(log::LevelFilter::Warn)
-        // - and per-module overrides
-        .level_for("hyper", log::LevelFilter::Info)
-        .level_for("discord_bridge", log::LevelFilter::Info)
-        // Output to stdout, files, and other Dispatch configurations
-        .chain(std::io::stdout())
-        .chain(fern::log_file("output.log").expect("Log file open failed"))
-        // Apply globally
-        .apply()
-        .expect("Failed to initialise logging");
+        .map(Arc::new);
 
     let token = env::var("BOT_TOKEN").expect("Expected a token in the environment");
 
     let options = poise::FrameworkOptions {
-        commands: vec![commands::data(), commands::join(), commands::leave(), commands::ping()],
+        commands: vec![
+            commands::data(),
+            commands::join(),
+            commands::leave(),
+            commands::ping(),
+            commands::volume(),
+            commands::rfmute(),
+            commands::controlop(),
+            commands::radioid(),
+            commands::callsign(),
+            commands::lastheard(),
+            commands::status(),
+        ],
         prefix_options: poise::PrefixFrameworkOptions {
             prefix: Some("!".into()),
             edit_tracker: Some(Arc::new(poise::EditTracker::for_timespan(
@@ -95,7 +212,9 @@ async fn main() {
         // Enforce command checks even for owners (enforced by default)
         // Set to true to bypass checks, which is useful for testing
         skip_checks_for_owners: false,
-        event_handler: |_ctx, _event, _framework, _data| Box::pin(async move { Ok(()) }),
+        event_handler: |ctx, event, _framework, data| {
+            Box::pin(handler::event_handler(ctx, event, data))
+        },
         ..Default::default()
     };
 
@@ -108,9 +227,52 @@ async fn main() {
                     ready.user.id.get()
                 );
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                Ok(Data {
-                    clients: Mutex::new(HashMap::new()),
-                })
+                let data = Data {
+                    config,
+                    bridges,
+                    settings: Arc::default(),
+                    radioid: Arc::new(RwLock::new(radioid)),
+                    registry: Arc::new(registry),
+                    lastheard: Arc::new(lastheard),
+                    started: Instant::now(),
+                    joining: Arc::default(),
+                    rejoining: Arc::default(),
+                    #[cfg(unix)]
+                    notifier,
+                };
+                let (startup_ctx, startup_data) = (ctx.clone(), data.clone());
+                tokio::spawn(async move {
+                    session::auto_join(&startup_ctx, &startup_data).await;
+                    #[cfg(unix)]
+                    if let Some(notifier) = startup_data.notifier.clone() {
+                        watchdog::run(startup_ctx, startup_data, notifier).await;
+                    }
+                });
+                tokio::spawn(shutdown_on_signal(
+                    ctx.clone(),
+                    data.clone(),
+                    framework.shard_manager().clone(),
+                ));
+                #[cfg(feature = "dashboard")]
+                if let Some(addr) = data.config.dashboard_addr {
+                    tokio::spawn(dashboard::serve(addr, ctx.clone(), data.clone()));
+                }
+                #[cfg(feature = "listen")]
+                if let Some(addr) = data.config.listen_addr {
+                    tokio::spawn(listen::serve(addr, data.clone()));
+                }
+                #[cfg(feature = "api")]
+                if let Some(addr) = data.config.api_addr {
+                    tokio::spawn(api::serve(
+                        addr,
+                        api::ApiState {
+                            ctx: ctx.clone(),
+                            data: data.clone(),
+                            shard_manager: framework.shard_manager().clone(),
+                        },
+                    ));
+                }
+                Ok(data)
             })
         })
         .options(options)
@@ -129,8 +291,20 @@ async fn main() {
         .await
         .expect("Error creating client");
 
+    #[cfg(feature = "metrics")]
+    if let Some(addr) = metrics_addr {
+        tokio::spawn(metrics::serve(
+            addr,
+            metrics::MetricsState {
+                backend: metrics_backend,
+                bridges: metrics_bridges,
+                shard_manager: client.shard_manager.clone(),
+            },
+        ));
+    }
+
     let _ = client
         .start()
         .await
-        .map_err(|why| println!("Client ended: {:?}", why));
+        .map_err(|why| warn!("Client ended: {:?}", why));
 }
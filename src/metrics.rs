@@ -0,0 +1,202 @@
+use axum::{extract::State, http::header, response::IntoResponse, routing::get, Router};
+use log::{info, warn};
+use poise::serenity_prelude as serenity;
+use serenity::all::ShardManager;
+use std::{collections::HashMap, fmt::Write, net::SocketAddr, sync::Arc};
+use tokio::sync::Mutex;
+
+use crate::{
+    backend::{Backend, VoiceBackend},
+    session::Bridge,
+};
+
+#[derive(Clone)]
+pub struct MetricsState {
+    /// What every bridge is connected to, a label of their metrics
+    pub backend: Backend,
+    pub bridges: Arc<Mutex<HashMap<u64, Bridge>>>,
+    pub shard_manager: Arc<ShardManager>,
+}
+
+/// Serve the Prometheus `/metrics` endpoint until the process exits
+pub async fn serve(addr: SocketAddr, state: MetricsState) {
+    let app = Router::new()
+        .route("/metrics", get(metrics))
+        .with_state(state);
+    let listener = match tokio::net::TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Failed to bind metrics endpoint {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("Serving metrics on http://{}/metrics", addr);
+    if let Err(e) = axum::serve(listener, app).await {
+        warn!("Metrics endpoint failed: {}", e);
+    }
+}
+
+/// Write one metric family, each sample labelled with its guild and backend
+fn family(
+    out: &mut String,
+    backend: &str,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: Vec<(u64, f64)>,
+) {
+    let _ = writeln!(out, "# HELP discord_bridge_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE discord_bridge_{} {}", name, kind);
+    write_samples(out, backend, name, samples);
+}
+
+fn write_samples(out: &mut String, backend: &str, name: &str, samples: Vec<(u64, f64)>) {
+    for (guild_id, value) in samples {
+        let _ = writeln!(
+            out,
+            "discord_bridge_{}{{guild=\"{}\",backend=\"{}\"}} {}",
+            name, guild_id, backend, value
+        );
+    }
+}
+
+async fn metrics(State(state): State<MetricsState>) -> impl IntoResponse {
+    let mut clients: Vec<_> = state
+        .bridges
+        .lock()
+        .await
+        .iter()
+        .map(|(&guild_id, bridge)| (guild_id, bridge.client.clone()))
+        .collect();
+    clients.sort_by_key(|(guild_id, _)| *guild_id);
+
+    let mut out = String::new();
+    // Lowercase like the BACKEND setting
+    let backend = state.backend.to_string().to_ascii_lowercase();
+    let per_bridge = |f: &dyn Fn(&dyn VoiceBackend) -> f64| {
+        clients
+            .iter()
+            .map(|(guild_id, client)| (*guild_id, f(client.as_ref())))
+            .collect::<Vec<_>>()
+    };
+
+    family(
+        &mut out,
+        &backend,
+        "packets_received_total",
+        "counter",
+        "Packets received from RF",
+        per_bridge(&|x| x.stats().packets_in() as f64),
+    );
+    family(
+        &mut out,
+        &backend,
+        "packets_sent_total",
+        "counter",
+        "Packets sent to RF",
+        per_bridge(&|x| x.stats().packets_out() as f64),
+    );
+    family(
+        &mut out,
+        &backend,
+        "packets_rejected_total",
+        "counter",
+        "Malformed or unknown packets received from RF",
+        per_bridge(&|x| x.stats().packets_rejected() as f64),
+    );
+    family(
+        &mut out,
+        &backend,
+        "received_bytes_total",
+        "counter",
+        "Bytes received from RF",
+        per_bridge(&|x| x.stats().bytes_in() as f64),
+    );
+    family(
+        &mut out,
+        &backend,
+        "sent_bytes_total",
+        "counter",
+        "Bytes sent to RF",
+        per_bridge(&|x| x.stats().bytes_out() as f64),
+    );
+    family(
+        &mut out,
+        &backend,
+        "sequence_gaps_total",
+        "counter",
+        "Discontinuities in the sequence numbers received from RF",
+        per_bridge(&|x| x.stats().sequence_gaps() as f64),
+    );
+    family(
+        &mut out,
+        &backend,
+        "rx_seconds_total",
+        "counter",
+        "Seconds of audio received from RF",
+        per_bridge(&|x| x.stats().samples_in() as f64 / x.sample_rate() as f64),
+    );
+    family(
+        &mut out,
+        &backend,
+        "tx_seconds_total",
+        "counter",
+        "Seconds of audio sent to RF",
+        per_bridge(&|x| x.stats().samples_out() as f64 / x.sample_rate() as f64),
+    );
+    family(
+        &mut out,
+        &backend,
+        "discord_users",
+        "gauge",
+        "Discord users known to the bridge",
+        per_bridge(&|x| x.stats().users().len() as f64),
+    );
+    family(
+        &mut out,
+        &backend,
+        "jitter_buffer_bytes",
+        "gauge",
+        "Audio waiting in the RF to Discord buffer",
+        per_bridge(&|x| x.stats().buffer_fill().0 as f64),
+    );
+    family(
+        &mut out,
+        &backend,
+        "resample_seconds",
+        "summary",
+        "Time spent resampling a chunk of audio",
+        Vec::new(),
+    );
+    write_samples(
+        &mut out,
+        &backend,
+        "resample_seconds_sum",
+        per_bridge(&|x| x.stats().resample_time().0.as_secs_f64()),
+    );
+    write_samples(
+        &mut out,
+        &backend,
+        "resample_seconds_count",
+        per_bridge(&|x| x.stats().resample_time().1 as f64),
+    );
+
+    let runners = state.shard_manager.runners.lock().await;
+    let _ = writeln!(
+        out,
+        "# HELP discord_bridge_gateway_latency_seconds Discord gateway heartbeat latency"
+    );
+    let _ = writeln!(out, "# TYPE discord_bridge_gateway_latency_seconds gauge");
+    for (shard_id, runner) in runners.iter() {
+        if let Some(latency) = runner.latency {
+            let _ = writeln!(
+                out,
+                "discord_bridge_gateway_latency_seconds{{shard=\"{}\"}} {}",
+                shard_id,
+                latency.as_secs_f64()
+            );
+        }
+    }
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], out)
+}
@@ -0,0 +1,682 @@
+use audiopus::{
+    coder::{Decoder, Encoder},
+    packet::Packet,
+    Application, Channels, MutSignals, SampleRate,
+};
+use discord_bridge::{
+    callsign::extract_callsign,
+    mumble_proto::{self as proto, MessageType, Ping, ServerSync, UserRemove, UserState},
+    mumble_voice as voice,
+};
+use log::{info, warn};
+use serenity::async_trait;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    io::{Error, ErrorKind},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    io::{ReadHalf, WriteHalf},
+    net::TcpStream,
+    sync::{mpsc, Mutex as AsyncMutex},
+    time::{timeout, Instant},
+};
+use tokio_rustls::{
+    client::TlsStream,
+    rustls::{
+        self,
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        crypto::{ring, CryptoProvider},
+        pki_types::{CertificateDer, ServerName, UnixTime},
+        ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+    },
+    TlsConnector,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    backend::{Talker, VoiceBackend, VoicePacket},
+    stats::BridgeStats,
+    usrp::FRAME_MS,
+};
+
+type Reader = ReadHalf<TlsStream<TcpStream>>;
+type Writer = WriteHalf<TlsStream<TcpStream>>;
+
+/// Mumble always carries 48 kHz audio
+const SAMPLE_RATE: usize = 48000;
+const FRAME_SIZE: usize = SAMPLE_RATE * FRAME_MS / 1000;
+const DEFAULT_PORT: u16 = 64738;
+/// Time allowed to connect and log in
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// The server drops clients that stay silent for 30 seconds
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+/// A transmission whose terminator was lost ends after this long without audio
+const TALK_TIMEOUT: Duration = Duration::from_millis(500);
+/// Mumble sequence numbers count 10 ms frames
+const SEQUENCE_PER_FRAME: u64 = FRAME_MS as u64 / 10;
+/// Received audio waiting for `recv`
+const INCOMING_BACKLOG: usize = 64;
+/// Delays between reconnection attempts, doubled after every failure
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A user connected to a Mumble server, speaking in one of its channels.
+/// Voice is tunnelled over the TLS control connection, which spares the
+/// encrypted UDP channel and works through any firewall letting TCP through
+pub struct MumbleClient {
+    server: Arc<Server>,
+
+    writer: Arc<AsyncMutex<Option<Writer>>>,
+    incoming_tx: mpsc::Sender<(VoicePacket, SocketAddr)>,
+    incoming_rx: AsyncMutex<mpsc::Receiver<(VoicePacket, SocketAddr)>>,
+    outgoing: Mutex<Outgoing>,
+    /// Stops the connection and the reconnection attempts
+    cancel: Mutex<CancellationToken>,
+    stats: Arc<BridgeStats>,
+}
+
+/// Where and as whom the client logs in
+struct Server {
+    server: String,
+    username: String,
+    password: Option<String>,
+    channel: Option<String>,
+    accept_invalid_certs: bool,
+}
+
+/// A logged in connection, before its tasks are started
+struct Connection {
+    reader: Reader,
+    writer: Writer,
+    peer: SocketAddr,
+    /// User names by session
+    users: HashMap<u32, String>,
+}
+
+/// Encoder state of the transmission to Mumble
+struct Outgoing {
+    encoder: Option<Encoder>,
+    pending: Vec<i16>,
+    sequence: u64,
+}
+
+impl MumbleClient {
+    /// Create a new MumbleClient
+    ///
+    /// server: `host` or `host:port` of the Mumble server
+    /// channel: Name of the channel to join, the root channel when None
+    /// accept_invalid_certs: Skip certificate checks, for self-signed servers
+    pub fn new(
+        server: String,
+        username: String,
+        password: Option<String>,
+        channel: Option<String>,
+        accept_invalid_certs: bool,
+    ) -> Self {
+        let (incoming_tx, incoming_rx) = mpsc::channel(INCOMING_BACKLOG);
+        Self {
+            server: Arc::new(Server {
+                server,
+                username,
+                password,
+                channel,
+                accept_invalid_certs,
+            }),
+
+            writer: Arc::default(),
+            incoming_tx,
+            incoming_rx: AsyncMutex::new(incoming_rx),
+            outgoing: Mutex::new(Outgoing {
+                encoder: None,
+                pending: Vec::new(),
+                sequence: 0,
+            }),
+            cancel: Mutex::default(),
+            stats: Arc::default(),
+        }
+    }
+
+    /// Log in, then keep the connection up until `disconnect`
+    pub async fn connect(&mut self) -> Result<(), Error> {
+        let connection = self.server.open().await?;
+        let cancel = CancellationToken::new();
+        *self.cancel.lock().unwrap() = cancel.clone();
+        tokio::spawn(run(
+            self.server.clone(),
+            connection,
+            self.writer.clone(),
+            self.incoming_tx.clone(),
+            self.stats.clone(),
+            cancel,
+        ));
+        Ok(())
+    }
+
+    async fn send_voice(&self, packet: Vec<u8>, samples: usize) -> Result<(), Error> {
+        let mut writer = self.writer.lock().await;
+        let Some(writer) = writer.as_mut() else {
+            return Ok(());
+        };
+        let frame = proto::frame(MessageType::UdpTunnel, &packet);
+        tokio::io::AsyncWriteExt::write_all(writer, &frame).await?;
+        self.stats.sent(frame.len(), samples);
+        Ok(())
+    }
+}
+
+impl Server {
+    /// Connect, log in and move to the configured channel
+    async fn open(&self) -> Result<Connection, Error> {
+        let (host, port) = match self.server.rsplit_once(':') {
+            Some((host, port)) if port.parse::<u16>().is_ok() => {
+                (host.trim_matches(['[', ']']), port.parse().unwrap())
+            }
+            _ => (self.server.as_str(), DEFAULT_PORT),
+        };
+        let tcp = timeout(CONNECT_TIMEOUT, TcpStream::connect((host, port)))
+            .await
+            .map_err(|_| Error::new(ErrorKind::TimedOut, "Connection timed out"))??;
+        tcp.set_nodelay(true)?;
+        let peer = tcp.peer_addr()?;
+        let server_name = ServerName::try_from(host.to_string())
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        let connector = TlsConnector::from(Arc::new(tls_config(self.accept_invalid_certs)));
+        let stream = timeout(CONNECT_TIMEOUT, connector.connect(server_name, tcp))
+            .await
+            .map_err(|_| Error::new(ErrorKind::TimedOut, "TLS handshake timed out"))??;
+        let (mut reader, mut writer) = tokio::io::split(stream);
+
+        let version = proto::Version {
+            version: Some(proto::VERSION),
+            release: Some(concat!("discord-bridge ", env!("CARGO_PKG_VERSION")).to_string()),
+            os: Some(std::env::consts::OS.to_string()),
+            os_version: None,
+        };
+        proto::write(&mut writer, MessageType::Version, &version).await?;
+        let authenticate = proto::Authenticate {
+            username: Some(self.username.clone()),
+            password: self.password.clone(),
+            opus: Some(true),
+        };
+        proto::write(&mut writer, MessageType::Authenticate, &authenticate).await?;
+
+        let login = timeout(CONNECT_TIMEOUT, login(&mut reader))
+            .await
+            .map_err(|_| Error::new(ErrorKind::TimedOut, "Login timed out"))??;
+        let session = login.session;
+        if let Some(channel) = &self.channel {
+            let channel_id = *login.channels.get(channel).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("No Mumble channel named {}", channel),
+                )
+            })?;
+            let state = UserState {
+                session: Some(session),
+                channel_id: Some(channel_id),
+                ..Default::default()
+            };
+            proto::write(&mut writer, MessageType::UserState, &state).await?;
+        }
+        info!(
+            "Logged in to Mumble server {} as {} (session {})",
+            self.server, self.username, session
+        );
+        Ok(Connection {
+            reader,
+            writer,
+            peer,
+            users: login.users,
+        })
+    }
+}
+
+/// Serve a connection until it is lost, then log in again with exponential
+/// backoff, until cancelled
+async fn run(
+    server: Arc<Server>,
+    mut connection: Connection,
+    writer: Arc<AsyncMutex<Option<Writer>>>,
+    sender: mpsc::Sender<(VoicePacket, SocketAddr)>,
+    stats: Arc<BridgeStats>,
+    cancel: CancellationToken,
+) {
+    loop {
+        *writer.lock().await = Some(connection.writer);
+        let incoming = Incoming {
+            sender: sender.clone(),
+            peer: connection.peer,
+            pending: Vec::new(),
+        };
+        // Cancelled by either task when the connection fails
+        let lost = cancel.child_token();
+        tokio::join!(
+            receive(
+                connection.reader,
+                connection.users,
+                incoming,
+                stats.clone(),
+                lost.clone(),
+            ),
+            ping(writer.clone(), lost),
+        );
+
+        let mut backoff = MIN_BACKOFF;
+        connection = loop {
+            tokio::select! {
+                _ = tokio::time::sleep(backoff) => {}
+                _ = cancel.cancelled() => return,
+            }
+            match server.open().await {
+                Ok(connection) => break connection,
+                Err(e) => warn!(
+                    "Failed to reconnect to Mumble server {}: {}, retrying in {:?}",
+                    server.server,
+                    e,
+                    (backoff * 2).min(MAX_BACKOFF)
+                ),
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        };
+    }
+}
+
+impl Outgoing {
+    /// Encode the complete frames of queued audio, the last one carrying the
+    /// terminator when the transmission ends
+    fn encode(&mut self, end: bool) -> Vec<(Vec<u8>, usize)> {
+        if self.encoder.is_none() {
+            match Encoder::new(SampleRate::Hz48000, Channels::Mono, Application::Voip) {
+                Ok(encoder) => self.encoder = Some(encoder),
+                Err(e) => {
+                    warn!("Failed to create the Mumble encoder: {}", e);
+                    return Vec::new();
+                }
+            }
+        }
+        if end {
+            // Pad the last frame with silence, or send one of silence to end
+            let len = self.pending.len().max(1).div_ceil(FRAME_SIZE) * FRAME_SIZE;
+            self.pending.resize(len, 0);
+        }
+        let encoder = self.encoder.as_ref().unwrap();
+        let mut output = [0u8; 4000];
+        let mut packets = Vec::new();
+        while self.pending.len() >= FRAME_SIZE {
+            let frame: Vec<i16> = self.pending.drain(..FRAME_SIZE).collect();
+            let terminator = end && self.pending.is_empty();
+            match encoder.encode(&frame, &mut output) {
+                Ok(len) => {
+                    packets.push((
+                        voice::encode(self.sequence, &output[..len], terminator),
+                        FRAME_SIZE,
+                    ));
+                }
+                Err(e) => warn!("Failed to encode Mumble audio: {}", e),
+            }
+            self.sequence += SEQUENCE_PER_FRAME;
+        }
+        packets
+    }
+}
+
+#[async_trait]
+impl VoiceBackend for MumbleClient {
+    fn peer(&self) -> String {
+        self.server.server.clone()
+    }
+
+    fn sample_rate(&self) -> usize {
+        SAMPLE_RATE
+    }
+
+    fn stats(&self) -> &Arc<BridgeStats> {
+        &self.stats
+    }
+
+    async fn recv(&self) -> Option<(VoicePacket, SocketAddr)> {
+        let cancel = self.cancel.lock().unwrap().clone();
+        let mut incoming = self.incoming_rx.lock().await;
+        tokio::select! {
+            biased;
+            packet = incoming.recv() => packet,
+            _ = cancel.cancelled() => None,
+        }
+    }
+
+    async fn send(&self, packet: VoicePacket) -> Result<(), Error> {
+        let packets = {
+            let mut outgoing = self.outgoing.lock().unwrap();
+            match packet {
+                // Mumble shows who talks from the account, there is no metadata to send
+                VoicePacket::Start(_) => {
+                    outgoing.pending.clear();
+                    Vec::new()
+                }
+                VoicePacket::Audio(audio) => {
+                    outgoing.pending.extend(audio);
+                    outgoing.encode(false)
+                }
+                VoicePacket::End => outgoing.encode(true),
+            }
+        };
+        for (packet, samples) in packets {
+            self.send_voice(packet, samples).await?;
+        }
+        Ok(())
+    }
+
+    fn disconnect(&self) {
+        self.cancel.lock().unwrap().cancel();
+    }
+}
+
+/// The state of the server sent before the login completes
+#[derive(Default)]
+struct Login {
+    session: u32,
+    /// Channel ids by name
+    channels: HashMap<String, u32>,
+    /// User names by session
+    users: HashMap<u32, String>,
+}
+
+/// Read the login replies up to the server sync
+async fn login(reader: &mut Reader) -> Result<Login, Error> {
+    let mut login = Login::default();
+    loop {
+        let (message_type, payload) = proto::read(reader).await?;
+        match message_type {
+            MessageType::ChannelState => {
+                let channel: proto::ChannelState = proto::decode(&payload)?;
+                if let (Some(id), Some(name)) = (channel.channel_id, channel.name) {
+                    login.channels.entry(name).or_insert(id);
+                }
+            }
+            MessageType::UserState => {
+                let user: UserState = proto::decode(&payload)?;
+                if let (Some(session), Some(name)) = (user.session, user.name) {
+                    login.users.insert(session, name);
+                }
+            }
+            MessageType::ServerSync => {
+                let sync: ServerSync = proto::decode(&payload)?;
+                login.session = sync.session.unwrap_or_default();
+                return Ok(login);
+            }
+            MessageType::Reject => {
+                let reject: proto::Reject = proto::decode(&payload)?;
+                return Err(Error::new(
+                    ErrorKind::PermissionDenied,
+                    format!(
+                        "Mumble server rejected the login: {}",
+                        reject.reason.unwrap_or_default()
+                    ),
+                ));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Decode the voice of other users until the connection closes. Only one
+/// user is forwarded at a time, like a repeater the first to talk holds it
+async fn receive(
+    mut reader: Reader,
+    mut users: HashMap<u32, String>,
+    mut incoming: Incoming,
+    stats: Arc<BridgeStats>,
+    cancel: CancellationToken,
+) {
+    let mut decoders: HashMap<u32, Decoder> = HashMap::new();
+    let mut talker: Option<u32> = None;
+    let mut last_audio = Instant::now();
+    let mut sequence_number = 0u32;
+    let mut output = vec![0i16; SAMPLE_RATE * 120 / 1000];
+
+    loop {
+        let message = tokio::select! {
+            message = proto::read(&mut reader) => message,
+            _ = tokio::time::sleep_until(last_audio + TALK_TIMEOUT), if talker.is_some() => {
+                talker = None;
+                incoming.end().await;
+                continue;
+            }
+            _ = cancel.cancelled() => break,
+        };
+        let (message_type, payload) = match message {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Mumble connection lost: {}, reconnecting", e);
+                break;
+            }
+        };
+        match message_type {
+            MessageType::UdpTunnel => {
+                let Some(packet) = voice::decode(&payload) else {
+                    stats.rejected(payload.len());
+                    continue;
+                };
+                if talker.is_some_and(|x| x != packet.session) {
+                    continue;
+                }
+                let decoder = match decoders.entry(packet.session) {
+                    Entry::Occupied(x) => x.into_mut(),
+                    Entry::Vacant(x) => match Decoder::new(SampleRate::Hz48000, Channels::Mono) {
+                        Ok(decoder) => x.insert(decoder),
+                        Err(e) => {
+                            warn!("Failed to create a Mumble decoder: {}", e);
+                            continue;
+                        }
+                    },
+                };
+                let samples = match decode(decoder, packet.opus, &mut output) {
+                    Ok(samples) => samples,
+                    Err(e) => {
+                        warn!("Failed to decode Mumble audio: {}", e);
+                        continue;
+                    }
+                };
+                stats.received(incoming.peer, sequence_number, payload.len(), samples);
+                sequence_number = sequence_number.wrapping_add(1);
+
+                if talker.is_none() {
+                    talker = Some(packet.session);
+                    let name = users.get(&packet.session).cloned();
+                    incoming.start(name).await;
+                }
+                last_audio = Instant::now();
+                incoming.audio(&output[..samples]).await;
+                if packet.terminator {
+                    talker = None;
+                    incoming.end().await;
+                }
+            }
+            MessageType::UserState => {
+                let Ok(state) = proto::decode::<UserState>(&payload) else {
+                    continue;
+                };
+                if let (Some(session), Some(name)) = (state.session, state.name) {
+                    users.insert(session, name);
+                }
+            }
+            MessageType::UserRemove => {
+                let Ok(remove) = proto::decode::<UserRemove>(&payload) else {
+                    continue;
+                };
+                if let Some(session) = remove.session {
+                    users.remove(&session);
+                    decoders.remove(&session);
+                    if talker == Some(session) {
+                        talker = None;
+                        incoming.end().await;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if talker.is_some() {
+        incoming.end().await;
+    }
+    cancel.cancel();
+}
+
+/// Cuts the decoded voice of the current talker into frames for `recv`
+struct Incoming {
+    sender: mpsc::Sender<(VoicePacket, SocketAddr)>,
+    peer: SocketAddr,
+    pending: Vec<i16>,
+}
+
+impl Incoming {
+    async fn send(&self, packet: VoicePacket) {
+        let _ = self.sender.send((packet, self.peer)).await;
+    }
+
+    /// Announce a talker, with the callsign found in their user name
+    async fn start(&mut self, name: Option<String>) {
+        self.pending.clear();
+        let callsign = name
+            .as_deref()
+            .and_then(extract_callsign)
+            .map(|x| x.to_string())
+            .unwrap_or_default();
+        let talker = Talker {
+            callsign,
+            name,
+            ..Default::default()
+        };
+        self.send(VoicePacket::Start(Some(talker))).await;
+    }
+
+    async fn audio(&mut self, audio: &[i16]) {
+        self.pending.extend_from_slice(audio);
+        while self.pending.len() >= FRAME_SIZE {
+            let frame = self.pending.drain(..FRAME_SIZE).collect();
+            self.send(VoicePacket::Audio(frame)).await;
+        }
+    }
+
+    /// Flush the last partial frame padded with silence, then end
+    async fn end(&mut self) {
+        if !self.pending.is_empty() {
+            let mut frame = std::mem::take(&mut self.pending);
+            frame.resize(FRAME_SIZE, 0);
+            self.send(VoicePacket::Audio(frame)).await;
+        }
+        self.send(VoicePacket::End).await;
+    }
+}
+
+/// Decode one Opus packet, an empty one ends a transmission without audio
+fn decode(decoder: &mut Decoder, opus: &[u8], output: &mut [i16]) -> audiopus::Result<usize> {
+    if opus.is_empty() {
+        return Ok(0);
+    }
+    decoder.decode(
+        Some(Packet::try_from(opus)?),
+        MutSignals::try_from(output)?,
+        false,
+    )
+}
+
+/// Keep the connection alive until cancelled, then close it
+async fn ping(writer: Arc<AsyncMutex<Option<Writer>>>, cancel: CancellationToken) {
+    let mut interval = tokio::time::interval(PING_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = cancel.cancelled() => break,
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut writer = writer.lock().await;
+        let Some(writer) = writer.as_mut() else {
+            break;
+        };
+        let ping = Ping {
+            timestamp: Some(timestamp),
+        };
+        if let Err(e) = proto::write(writer, MessageType::Ping, &ping).await {
+            warn!("Failed to ping the Mumble server: {}", e);
+            cancel.cancel();
+            break;
+        }
+    }
+    *writer.lock().await = None;
+}
+
+fn tls_config(accept_invalid_certs: bool) -> ClientConfig {
+    let provider = Arc::new(ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .expect("The ring provider supports the default TLS versions");
+    if accept_invalid_certs {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AcceptAnyCert(provider)))
+            .with_no_client_auth()
+    } else {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        builder.with_root_certificates(roots).with_no_client_auth()
+    }
+}
+
+/// Most Mumble servers run with the self-signed certificate generated on
+/// their first start, this accepts any certificate but still checks the
+/// handshake signatures
+#[derive(Debug)]
+struct AcceptAnyCert(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
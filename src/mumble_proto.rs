@@ -0,0 +1,163 @@
+//! The subset of the Mumble control protocol (`Mumble.proto`) the bridge uses
+
+use prost::Message;
+use std::io::{Error, ErrorKind};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Largest control message accepted, the server limits them to 8 MiB
+const MAX_MESSAGE_LEN: usize = 8 * 1024 * 1024;
+
+/// Protocol version announced to the server, 1.4.0 keeps the legacy voice format
+pub const VERSION: u32 = 1 << 16 | 4 << 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageType {
+    Version = 0,
+    UdpTunnel = 1,
+    Authenticate = 2,
+    Ping = 3,
+    Reject = 4,
+    ServerSync = 5,
+    ChannelState = 7,
+    UserRemove = 8,
+    UserState = 9,
+}
+
+impl MessageType {
+    fn from_u16(value: u16) -> Option<Self> {
+        Some(match value {
+            0 => Self::Version,
+            1 => Self::UdpTunnel,
+            2 => Self::Authenticate,
+            3 => Self::Ping,
+            4 => Self::Reject,
+            5 => Self::ServerSync,
+            7 => Self::ChannelState,
+            8 => Self::UserRemove,
+            9 => Self::UserState,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Version {
+    #[prost(uint32, optional, tag = "1")]
+    pub version: Option<u32>,
+    #[prost(string, optional, tag = "2")]
+    pub release: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub os: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub os_version: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Authenticate {
+    #[prost(string, optional, tag = "1")]
+    pub username: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub password: Option<String>,
+    #[prost(bool, optional, tag = "5")]
+    pub opus: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Ping {
+    #[prost(uint64, optional, tag = "1")]
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Reject {
+    #[prost(int32, optional, tag = "1")]
+    pub r#type: Option<i32>,
+    #[prost(string, optional, tag = "2")]
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ServerSync {
+    #[prost(uint32, optional, tag = "1")]
+    pub session: Option<u32>,
+    #[prost(uint32, optional, tag = "2")]
+    pub max_bandwidth: Option<u32>,
+    #[prost(string, optional, tag = "3")]
+    pub welcome_text: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ChannelState {
+    #[prost(uint32, optional, tag = "1")]
+    pub channel_id: Option<u32>,
+    #[prost(uint32, optional, tag = "2")]
+    pub parent: Option<u32>,
+    #[prost(string, optional, tag = "3")]
+    pub name: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct UserRemove {
+    #[prost(uint32, optional, tag = "1")]
+    pub session: Option<u32>,
+    #[prost(uint32, optional, tag = "2")]
+    pub actor: Option<u32>,
+    #[prost(string, optional, tag = "3")]
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct UserState {
+    #[prost(uint32, optional, tag = "1")]
+    pub session: Option<u32>,
+    #[prost(uint32, optional, tag = "2")]
+    pub actor: Option<u32>,
+    #[prost(string, optional, tag = "3")]
+    pub name: Option<String>,
+    #[prost(uint32, optional, tag = "5")]
+    pub channel_id: Option<u32>,
+}
+
+/// Read the next known message, skipping the types the bridge has no use for
+pub async fn read<R: AsyncRead + Unpin>(reader: &mut R) -> Result<(MessageType, Vec<u8>), Error> {
+    loop {
+        let mut header = [0u8; 6];
+        reader.read_exact(&mut header).await?;
+        let message_type = u16::from_be_bytes([header[0], header[1]]);
+        let len = u32::from_be_bytes([header[2], header[3], header[4], header[5]]) as usize;
+        if len > MAX_MESSAGE_LEN {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Mumble message too large",
+            ));
+        }
+        let mut payload = vec![0u8; len];
+        reader.read_exact(&mut payload).await?;
+        if let Some(message_type) = MessageType::from_u16(message_type) {
+            return Ok((message_type, payload));
+        }
+    }
+}
+
+/// Frame a message with its type and length
+pub fn frame(message_type: MessageType, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(6 + payload.len());
+    out.extend_from_slice(&(message_type as u16).to_be_bytes());
+    out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+pub async fn write<W: AsyncWrite + Unpin, M: Message>(
+    writer: &mut W,
+    message_type: MessageType,
+    message: &M,
+) -> Result<(), Error> {
+    writer
+        .write_all(&frame(message_type, &message.encode_to_vec()))
+        .await
+}
+
+pub fn decode<M: Message + Default>(payload: &[u8]) -> Result<M, Error> {
+    M::decode(payload).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
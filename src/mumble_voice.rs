@@ -0,0 +1,88 @@
+//! Voice packets in the legacy format tunnelled over the control channel
+
+/// Codec of a voice packet, in the top 3 bits of its header
+const TYPE_OPUS: u8 = 4;
+/// Set in the Opus length of the last packet of a transmission
+const TERMINATOR: u64 = 0x2000;
+const LENGTH_MASK: u64 = 0x1fff;
+
+/// An Opus packet received from another user
+pub struct IncomingVoice<'a> {
+    pub session: u32,
+    pub opus: &'a [u8],
+    /// Last packet of the transmission
+    pub terminator: bool,
+}
+
+/// Encode an Opus packet sent to the current channel
+pub fn encode(sequence: u64, opus: &[u8], terminator: bool) -> Vec<u8> {
+    let mut out = vec![TYPE_OPUS << 5];
+    write_varint(&mut out, sequence);
+    let len = opus.len() as u64 & LENGTH_MASK;
+    write_varint(&mut out, if terminator { len | TERMINATOR } else { len });
+    out.extend_from_slice(opus);
+    out
+}
+
+/// Decode a packet from the server, None for pings and other codecs
+pub fn decode(packet: &[u8]) -> Option<IncomingVoice<'_>> {
+    let (&header, mut rest) = packet.split_first()?;
+    if header >> 5 != TYPE_OPUS {
+        return None;
+    }
+    let session = read_varint(&mut rest)? as u32;
+    // The sequence number, TCP delivers in order
+    read_varint(&mut rest)?;
+    let len = read_varint(&mut rest)?;
+    let opus = rest.get(..(len & LENGTH_MASK) as usize)?;
+    Some(IncomingVoice {
+        session,
+        opus,
+        terminator: len & TERMINATOR != 0,
+    })
+}
+
+/// Mumble's variable length integers, the leading bits give the length
+pub fn write_varint(out: &mut Vec<u8>, value: u64) {
+    match value {
+        0..0x80 => out.push(value as u8),
+        0x80..0x4000 => out.extend_from_slice(&[0x80 | (value >> 8) as u8, value as u8]),
+        0x4000..0x20_0000 => {
+            out.extend_from_slice(&[0xc0 | (value >> 16) as u8, (value >> 8) as u8, value as u8])
+        }
+        0x20_0000..0x1000_0000 => out.extend_from_slice(&[
+            0xe0 | (value >> 24) as u8,
+            (value >> 16) as u8,
+            (value >> 8) as u8,
+            value as u8,
+        ]),
+        0x1000_0000..0x1_0000_0000 => {
+            out.push(0xf0);
+            out.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(0xf4);
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+/// Read a variable length integer, advancing `input` past it
+pub fn read_varint(input: &mut &[u8]) -> Option<u64> {
+    let mut take = |n: usize| -> Option<u64> {
+        let bytes = input.get(..n)?;
+        *input = &input[n..];
+        Some(bytes.iter().fold(0, |acc, &x| acc << 8 | x as u64))
+    };
+    let first = take(1)?;
+    Some(match first {
+        0x00..0x80 => first,
+        0x80..0xc0 => (first & 0x3f) << 8 | take(1)?,
+        0xc0..0xe0 => (first & 0x1f) << 16 | take(2)?,
+        0xe0..0xf0 => (first & 0x0f) << 24 | take(3)?,
+        0xf0..0xf4 => take(4)?,
+        0xf4..0xf8 => take(8)?,
+        // Negative numbers, never used for sessions, sequences or lengths
+        _ => return None,
+    })
+}
@@ -0,0 +1,129 @@
+/// Writes an Opus stream into Ogg pages (RFC 7845), one page per packet so
+/// every page can be sent to listeners as soon as it is encoded
+pub struct OggOpusWriter {
+    serial: u32,
+    sequence: u32,
+    /// Position of the end of the last packet, in 48 kHz samples
+    granule: u64,
+}
+
+/// Samples the decoder drops at the start of the stream, libopus' lookahead at 48 kHz
+pub const PRE_SKIP: u16 = 312;
+
+const HEADER_TYPE_BOS: u8 = 0x02;
+const HEADER_TYPE_EOS: u8 = 0x04;
+
+impl OggOpusWriter {
+    pub fn new(serial: u32) -> Self {
+        Self {
+            serial,
+            sequence: 0,
+            granule: 0,
+        }
+    }
+
+    /// The identification and comment header pages that start the stream,
+    /// `comments` are `FIELD=value` pairs such as `TITLE=...`
+    pub fn headers(
+        &mut self,
+        channels: u8,
+        input_sample_rate: u32,
+        comments: &[String],
+    ) -> Vec<u8> {
+        let mut head = b"OpusHead".to_vec();
+        head.push(1); // Version
+        head.push(channels);
+        head.extend_from_slice(&PRE_SKIP.to_le_bytes());
+        head.extend_from_slice(&input_sample_rate.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes()); // Output gain
+        head.push(0); // Mono or stereo, no channel mapping table
+
+        let vendor = concat!("discord-bridge ", env!("CARGO_PKG_VERSION"));
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor.as_bytes());
+        tags.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            tags.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            tags.extend_from_slice(comment.as_bytes());
+        }
+
+        let mut out = self.page(&head, HEADER_TYPE_BOS, 0);
+        out.extend(self.page(&tags, 0, 0));
+        out
+    }
+
+    /// A page holding one Opus packet of `samples` samples at 48 kHz
+    pub fn packet(&mut self, packet: &[u8], samples: u64) -> Vec<u8> {
+        self.granule += samples;
+        self.page(packet, 0, self.granule)
+    }
+
+    /// End the stream and start a new one with `serial`, a chained stream is
+    /// how Ogg changes its comments mid-stream. Returns the empty page that
+    /// ends the stream, the new headers must follow it
+    pub fn chain(&mut self, serial: u32) -> Vec<u8> {
+        let end = self.write_page(&[], &[], HEADER_TYPE_EOS, self.granule);
+        *self = Self::new(serial);
+        end
+    }
+
+    fn page(&mut self, data: &[u8], header_type: u8, granule: u64) -> Vec<u8> {
+        // Lacing values, a packet ending on a multiple of 255 needs a final 0
+        let mut segments = vec![255u8; data.len() / 255];
+        segments.push((data.len() % 255) as u8);
+        assert!(segments.len() <= 255, "Opus packet too large for one page");
+        self.write_page(&segments, data, header_type, granule)
+    }
+
+    fn write_page(
+        &mut self,
+        segments: &[u8],
+        data: &[u8],
+        header_type: u8,
+        granule: u64,
+    ) -> Vec<u8> {
+        let mut page = Vec::with_capacity(27 + segments.len() + data.len());
+        page.extend_from_slice(b"OggS");
+        page.push(0); // Version
+        page.push(header_type);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&self.serial.to_le_bytes());
+        page.extend_from_slice(&self.sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]); // CRC, filled in below
+        page.push(segments.len() as u8);
+        page.extend_from_slice(segments);
+        page.extend_from_slice(data);
+
+        let crc = crc32(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        self.sequence += 1;
+        page
+    }
+}
+
+/// The CRC of Ogg pages: polynomial 0x04c11db7, not reflected, zero initial value
+pub fn crc32(data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = (i as u32) << 24;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 0x8000_0000 != 0 {
+                    (crc << 1) ^ 0x04c1_1db7
+                } else {
+                    crc << 1
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+    data.iter().fold(0, |crc, &x| {
+        (crc << 8) ^ TABLE[((crc >> 24) as u8 ^ x) as usize]
+    })
+}
@@ -0,0 +1,117 @@
+use std::{collections::HashMap, fs::File, io, path::Path, sync::Arc};
+
+/// A single user from a RadioID style database dump
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RadioIdRecord {
+    pub dmr_id: u32,
+    pub callsign: String,
+    pub name: String,
+    pub city: String,
+    pub state: String,
+    pub country: String,
+}
+
+/// Offline DMR ID <-> callsign lookup, loaded from a RadioID `user.csv` dump
+#[derive(Default)]
+pub struct RadioIdDatabase {
+    by_dmr_id: HashMap<u32, Arc<RadioIdRecord>>,
+    by_callsign: HashMap<String, Vec<Arc<RadioIdRecord>>>,
+}
+
+/// Columns of the RadioID dump, matched case insensitively against the header
+const DMR_ID_COLUMNS: &[&str] = &["radio_id", "dmr_id", "id"];
+const CALLSIGN_COLUMNS: &[&str] = &["callsign"];
+const FIRST_NAME_COLUMNS: &[&str] = &["first_name", "fname", "name"];
+const LAST_NAME_COLUMNS: &[&str] = &["last_name", "surname"];
+const CITY_COLUMNS: &[&str] = &["city"];
+const STATE_COLUMNS: &[&str] = &["state"];
+const COUNTRY_COLUMNS: &[&str] = &["country"];
+
+fn column(headers: &csv::StringRecord, names: &[&str]) -> Option<usize> {
+    headers
+        .iter()
+        .position(|x| names.contains(&x.trim().to_ascii_lowercase().as_str()))
+}
+
+impl RadioIdDatabase {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_reader(File::open(path)?)
+    }
+
+    /// Parse a CSV dump with a header row. Rows with a malformed DMR ID are skipped.
+    pub fn from_reader(reader: impl io::Read) -> io::Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let headers = reader.headers()?.clone();
+
+        let missing =
+            |name| io::Error::new(io::ErrorKind::InvalidData, format!("No {} column", name));
+        let dmr_id = column(&headers, DMR_ID_COLUMNS).ok_or_else(|| missing("DMR ID"))?;
+        let callsign = column(&headers, CALLSIGN_COLUMNS).ok_or_else(|| missing("callsign"))?;
+        let first_name = column(&headers, FIRST_NAME_COLUMNS);
+        let last_name = column(&headers, LAST_NAME_COLUMNS);
+        let city = column(&headers, CITY_COLUMNS);
+        let state = column(&headers, STATE_COLUMNS);
+        let country = column(&headers, COUNTRY_COLUMNS);
+
+        let mut database = Self::default();
+        for row in reader.records() {
+            let row = row?;
+            let field = |index: Option<usize>| {
+                index
+                    .and_then(|i| row.get(i))
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let Ok(id) = field(Some(dmr_id)).parse() else {
+                continue;
+            };
+            let name = [field(first_name), field(last_name)]
+                .into_iter()
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            database.insert(RadioIdRecord {
+                dmr_id: id,
+                callsign: field(Some(callsign)).to_ascii_uppercase(),
+                name,
+                city: field(city),
+                state: field(state),
+                country: field(country),
+            });
+        }
+        Ok(database)
+    }
+
+    pub fn insert(&mut self, record: RadioIdRecord) {
+        let record = Arc::new(record);
+        self.by_callsign
+            .entry(record.callsign.clone())
+            .or_default()
+            .push(record.clone());
+        self.by_dmr_id.insert(record.dmr_id, record);
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_dmr_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_dmr_id.is_empty()
+    }
+
+    pub fn by_dmr_id(&self, dmr_id: u32) -> Option<Arc<RadioIdRecord>> {
+        self.by_dmr_id.get(&dmr_id).cloned()
+    }
+
+    /// All records of a callsign, operators often own several DMR IDs
+    pub fn by_callsign(&self, callsign: &str) -> &[Arc<RadioIdRecord>] {
+        self.by_callsign
+            .get(&callsign.to_ascii_uppercase())
+            .map(|x| x.as_slice())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+use serenity::model::id::UserId;
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::callsign::Callsign;
+
+/// A callsign registered by a Discord user
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    pub callsign: String,
+    /// Checked by an admin, cleared when the callsign changes
+    #[serde(default)]
+    pub verified: bool,
+    /// Only admins can change a locked entry
+    #[serde(default)]
+    pub locked: bool,
+}
+
+#[derive(Debug)]
+pub enum RegistryError {
+    Locked,
+    /// The callsign is verified for another user
+    Taken(UserId),
+    Io(io::Error),
+}
+
+impl From<io::Error> for RegistryError {
+    fn from(e: io::Error) -> Self {
+        RegistryError::Io(e)
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::Locked => write!(f, "the entry is locked by an admin"),
+            RegistryError::Taken(user_id) => {
+                write!(f, "the callsign is verified for another user ({})", user_id)
+            }
+            RegistryError::Io(e) => write!(f, "failed to save the registry: {}", e),
+        }
+    }
+}
+
+/// Callsigns registered with `/callsign set`, stored in a JSON file
+pub struct CallsignRegistry {
+    path: Arc<PathBuf>,
+    entries: Mutex<HashMap<u64, RegistryEntry>>,
+    /// Held from a change until its snapshot is written, so saves land in order
+    saving: tokio::sync::Mutex<()>,
+}
+
+impl CallsignRegistry {
+    /// Load the registry, a missing file is an empty registry
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let entries = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            path: Arc::new(path),
+            entries: Mutex::new(entries),
+            saving: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// Write a snapshot of the entries off the async runtime, to a temporary
+    /// file first so a crash never leaves a truncated registry
+    async fn save(&self, snapshot: HashMap<u64, RegistryEntry>) -> io::Result<()> {
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || {
+            let tmp = path.with_extension("json.tmp");
+            fs::write(&tmp, serde_json::to_vec_pretty(&snapshot)?)?;
+            fs::rename(tmp, &*path)
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+
+    pub fn get(&self, user_id: UserId) -> Option<RegistryEntry> {
+        self.entries.lock().unwrap().get(&user_id.get()).cloned()
+    }
+
+    /// Register a callsign for a user, `force` bypasses the lock for admins.
+    /// A callsign verified for another user is taken whatever its prefix or
+    /// suffix, `W1AW/P` is still `W1AW`
+    pub async fn set(
+        &self,
+        user_id: UserId,
+        callsign: &Callsign,
+        force: bool,
+    ) -> Result<(), RegistryError> {
+        let _saving = self.saving.lock().await;
+        let snapshot = {
+            let mut entries = self.entries.lock().unwrap();
+            if let Some((&owner, _)) = entries.iter().find(|(&id, x)| {
+                id != user_id.get()
+                    && x.verified
+                    && Callsign::parse(&x.callsign).is_some_and(|x| x.base == callsign.base)
+            }) {
+                return Err(RegistryError::Taken(UserId::new(owner)));
+            }
+            let callsign = callsign.to_string();
+            let entry = entries.get(&user_id.get());
+            if entry.is_some_and(|x| x.locked) && !force {
+                return Err(RegistryError::Locked);
+            }
+            if entry.is_some_and(|x| x.callsign == callsign) {
+                return Ok(());
+            }
+            let locked = entry.is_some_and(|x| x.locked);
+            entries.insert(
+                user_id.get(),
+                RegistryEntry {
+                    callsign,
+                    verified: false,
+                    locked,
+                },
+            );
+            entries.clone()
+        };
+        self.save(snapshot).await?;
+        Ok(())
+    }
+
+    /// Returns the removed entry
+    pub async fn clear(
+        &self,
+        user_id: UserId,
+        force: bool,
+    ) -> Result<Option<RegistryEntry>, RegistryError> {
+        let _saving = self.saving.lock().await;
+        let (removed, snapshot) = {
+            let mut entries = self.entries.lock().unwrap();
+            if entries.get(&user_id.get()).is_some_and(|x| x.locked) && !force {
+                return Err(RegistryError::Locked);
+            }
+            let removed = entries.remove(&user_id.get());
+            (removed, entries.clone())
+        };
+        if removed.is_some() {
+            self.save(snapshot).await?;
+        }
+        Ok(removed)
+    }
+
+    /// Update an existing entry, returns the new entry or None if the user has none
+    async fn update(
+        &self,
+        user_id: UserId,
+        f: impl FnOnce(&mut RegistryEntry),
+    ) -> io::Result<Option<RegistryEntry>> {
+        let _saving = self.saving.lock().await;
+        let (entry, snapshot) = {
+            let mut entries = self.entries.lock().unwrap();
+            let Some(entry) = entries.get_mut(&user_id.get()) else {
+                return Ok(None);
+            };
+            f(entry);
+            let entry = entry.clone();
+            (entry, entries.clone())
+        };
+        self.save(snapshot).await?;
+        Ok(Some(entry))
+    }
+
+    pub async fn set_verified(
+        &self,
+        user_id: UserId,
+        verified: bool,
+    ) -> io::Result<Option<RegistryEntry>> {
+        self.update(user_id, |x| x.verified = verified).await
+    }
+
+    pub async fn set_locked(
+        &self,
+        user_id: UserId,
+        locked: bool,
+    ) -> io::Result<Option<RegistryEntry>> {
+        self.update(user_id, |x| x.locked = locked).await
+    }
+}
@@ -0,0 +1,100 @@
+use rubato::{
+    FftFixedIn, ResamplerConstructionError, SincFixedIn, SincInterpolationParameters,
+    SincInterpolationType, VecResampler, WindowFunction,
+};
+use std::{fmt, str::FromStr};
+
+/// Resampler presets, trading audio quality for CPU time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ResamplerQuality {
+    /// Polyphase FFT resampler, cheapest by far for the fixed 8k/16k <-> 48k ratios
+    Fast,
+    /// Short sinc filter
+    Balanced,
+    /// Long sinc filter, the original bridge setting
+    #[default]
+    High,
+}
+
+impl FromStr for ResamplerQuality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fast" | "fft" => Ok(Self::Fast),
+            "balanced" => Ok(Self::Balanced),
+            "high" | "hq" => Ok(Self::High),
+            _ => Err(format!("Unknown resampler quality: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ResamplerQuality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fast => write!(f, "fast"),
+            Self::Balanced => write!(f, "balanced"),
+            Self::High => write!(f, "high"),
+        }
+    }
+}
+
+/// A mono resampler with a fixed input chunk size
+pub struct MonoResampler {
+    inner: Box<dyn VecResampler<f64>>,
+}
+
+impl MonoResampler {
+    /// Create a new resampler converting `chunk_size` frames at `from_rate` per call
+    pub fn new(
+        quality: ResamplerQuality,
+        from_rate: usize,
+        to_rate: usize,
+        chunk_size: usize,
+    ) -> Result<Self, ResamplerConstructionError> {
+        // The sinc resamplers only check the ratio, which a zero rate makes infinite
+        if from_rate == 0 || to_rate == 0 {
+            return Err(ResamplerConstructionError::InvalidSampleRate {
+                input: from_rate,
+                output: to_rate,
+            });
+        }
+        let ratio = to_rate as f64 / from_rate as f64;
+        let inner: Box<dyn VecResampler<f64>> = match quality {
+            ResamplerQuality::Fast => {
+                Box::new(FftFixedIn::new(from_rate, to_rate, chunk_size, 1, 1)?)
+            }
+            ResamplerQuality::Balanced => {
+                let params = SincInterpolationParameters {
+                    sinc_len: 64,
+                    f_cutoff: 0.91,
+                    interpolation: SincInterpolationType::Linear,
+                    oversampling_factor: 128,
+                    window: WindowFunction::BlackmanHarris2,
+                };
+                Box::new(SincFixedIn::new(ratio, 2.0, params, chunk_size, 1)?)
+            }
+            ResamplerQuality::High => {
+                let params = SincInterpolationParameters {
+                    sinc_len: 256,
+                    f_cutoff: 0.95,
+                    interpolation: SincInterpolationType::Linear,
+                    oversampling_factor: 256,
+                    window: WindowFunction::BlackmanHarris2,
+                };
+                Box::new(SincFixedIn::new(ratio, 2.0, params, chunk_size, 1)?)
+            }
+        };
+        Ok(Self { inner })
+    }
+
+    /// Number of input frames expected by `process`
+    pub fn input_frames(&self) -> usize {
+        self.inner.input_frames_next()
+    }
+
+    /// Resample one chunk of audio, returns None if the chunk has the wrong size
+    pub fn process(&mut self, input: Vec<f64>) -> Option<Vec<f64>> {
+        self.inner.process(&[input], None).ok()?.into_iter().next()
+    }
+}
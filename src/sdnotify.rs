@@ -0,0 +1,82 @@
+use std::{env, io, os::unix::net::UnixDatagram, path::Path, time::Duration};
+
+/// Sends service state to systemd over `NOTIFY_SOCKET`, for `Type=notify` units
+pub struct Notifier {
+    socket: UnixDatagram,
+    /// How often systemd expects `WATCHDOG=1`, from `WATCHDOG_USEC`
+    watchdog: Option<Duration>,
+}
+
+impl Notifier {
+    /// None when not started by systemd with a notify socket
+    pub fn from_env() -> io::Result<Option<Self>> {
+        let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+            return Ok(None);
+        };
+        let mut notifier = Self::connect(path)?;
+        notifier.watchdog = watchdog_interval(
+            env::var("WATCHDOG_USEC").ok().as_deref(),
+            env::var("WATCHDOG_PID").ok().as_deref(),
+        );
+        Ok(Some(notifier))
+    }
+
+    /// Connect to a notify socket, a leading `@` is an abstract socket
+    pub fn connect(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let socket = UnixDatagram::unbound()?;
+        match path.to_str().and_then(|x| x.strip_prefix('@')) {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+                socket.connect_addr(&SocketAddr::from_abstract_name(name)?)?
+            }
+            _ => socket.connect(path)?,
+        }
+        Ok(Self {
+            socket,
+            watchdog: None,
+        })
+    }
+
+    /// Interval systemd expects watchdog pings at, None when the watchdog is off
+    pub fn watchdog(&self) -> Option<Duration> {
+        self.watchdog
+    }
+
+    /// Send newline separated `KEY=VALUE` assignments
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        self.socket.send(state.as_bytes()).map(|_| ())
+    }
+
+    pub fn ready(&self) -> io::Result<()> {
+        self.notify("READY=1")
+    }
+
+    pub fn stopping(&self) -> io::Result<()> {
+        self.notify("STOPPING=1")
+    }
+
+    pub fn watchdog_ping(&self) -> io::Result<()> {
+        self.notify("WATCHDOG=1")
+    }
+
+    /// Free form status shown by `systemctl status`, on a single line
+    pub fn status(&self, status: &str) -> io::Result<()> {
+        self.notify(&format!("STATUS={}", status.replace('\n', " ")))
+    }
+}
+
+/// Watchdog interval from `WATCHDOG_USEC`, when `WATCHDOG_PID` is unset or ours
+pub fn watchdog_interval(usec: Option<&str>, pid: Option<&str>) -> Option<Duration> {
+    if let Some(pid) = pid {
+        if pid.parse() != Ok(std::process::id()) {
+            return None;
+        }
+    }
+    usec?
+        .parse()
+        .ok()
+        .filter(|&x| x > 0)
+        .map(Duration::from_micros)
+}
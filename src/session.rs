@@ -0,0 +1,691 @@
+use chrono::prelude::Utc;
+use discord_bridge::{
+    audio::{Mixer, Panner},
+    lastheard::{Direction, Transmission},
+    resample::MonoResampler,
+};
+use log::{info, warn};
+use poise::serenity_prelude as serenity;
+use serenity::{
+    all::{ChannelId, GuildId, Member, VoiceState},
+    async_trait,
+    client::Context,
+};
+use songbird::{
+    input::{AsyncAdapterStream, AsyncReadOnlySource, File, RawAdapter},
+    CoreEvent, Event, EventContext, EventHandler as VoiceEventHandler,
+};
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{io::AsyncWriteExt, task::JoinHandle, time::MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+#[cfg(feature = "icecast")]
+use crate::icecast;
+#[cfg(feature = "listen")]
+use crate::listen::ListenMixer;
+use crate::{
+    backend::{self, Talker, VoiceBackend, VoicePacket},
+    bridge::BridgeEventHandler,
+    control_op,
+    stats::CountingReader,
+    status::StatusUpdater,
+    usrp::FRAME_MS,
+    Data, Error,
+};
+
+/// Longest wait for the tasks of a bridge to stop when leaving
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 20ms of 48kHz stereo audio
+const STEREO_FRAME: usize = 48 * FRAME_MS * 2;
+
+/// Audio queued per RF source before the oldest is dropped, 100ms
+const MIXER_CAPACITY: usize = 5 * STEREO_FRAME;
+
+/// An RF source sending nothing for this long lost the End of its
+/// transmission, which is ended as if it came
+const RF_SOURCE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// An RF source being heard, with its own resampler and stereo gains
+struct RfSource {
+    resampler: MonoResampler,
+    gains: (f32, f32),
+    last_heard: Instant,
+}
+
+/// A bridged guild, owning its backend and the tasks serving it
+pub struct Bridge {
+    pub client: Arc<dyn VoiceBackend>,
+    /// Audio of both directions, for the listen stream and Icecast
+    #[cfg(feature = "listen")]
+    pub listen: Arc<ListenMixer>,
+    handler: BridgeEventHandler,
+    cancel: CancellationToken,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Bridge {
+    /// Stop the tasks and close the backend, then wait for the tasks to finish
+    pub async fn shutdown(mut self) -> Result<(), Error> {
+        self.handler.end_transmission().await;
+        self.cancel.cancel();
+        self.client.disconnect();
+        let mut failures = Vec::new();
+        for mut task in std::mem::take(&mut self.tasks) {
+            match tokio::time::timeout(SHUTDOWN_TIMEOUT, &mut task).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => failures.push(format!("bridge task failed: {}", e)),
+                Err(_) => {
+                    task.abort();
+                    failures.push("bridge task did not stop in time".to_string());
+                }
+            }
+        }
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(failures.join(", ").into())
+        }
+    }
+
+    /// Whether none of the tasks has stopped
+    pub fn is_alive(&self) -> bool {
+        !self.tasks.iter().any(JoinHandle::is_finished)
+    }
+}
+
+impl Drop for Bridge {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+        self.client.disconnect();
+    }
+}
+
+async fn channel_name(ctx: &Context, channel_id: ChannelId) -> String {
+    channel_id
+        .name(ctx)
+        .await
+        .unwrap_or("{unknown}".to_string())
+}
+
+/// Guild being joined, released when the join returns
+struct JoinSlot {
+    joining: Arc<std::sync::Mutex<HashSet<u64>>>,
+    guild_id: u64,
+}
+
+impl JoinSlot {
+    /// Reserve a guild that is neither bridged nor being joined
+    async fn reserve(data: &Data, guild_id: GuildId) -> Option<Self> {
+        let bridges = data.bridges.lock().await;
+        let reserved = !bridges.contains_key(&guild_id.get())
+            && data.joining.lock().unwrap().insert(guild_id.get());
+        reserved.then(|| Self {
+            joining: data.joining.clone(),
+            guild_id: guild_id.get(),
+        })
+    }
+}
+
+impl Drop for JoinSlot {
+    fn drop(&mut self) {
+        self.joining.lock().unwrap().remove(&self.guild_id);
+    }
+}
+
+/// Join a voice channel and bridge it with the configured backend
+pub async fn join(
+    ctx: &Context,
+    data: &Data,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Result<(), Error> {
+    // Held until the bridge is inserted, so a concurrent join cannot get past this
+    let Some(_slot) = JoinSlot::reserve(data, guild_id).await else {
+        return Err("Already bridged in this guild, leave first".into());
+    };
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    let handler_lock = manager.join(guild_id, channel_id).await?;
+    let mut handler = handler_lock.lock().await;
+
+    let config = &data.config;
+    let usrp_sample_rate = data
+        .guild_settings(guild_id)
+        .await
+        .usrp_sample_rate()
+        .unwrap_or(config.usrp_sample_rate);
+    let client = match backend::connect(config, usrp_sample_rate).await {
+        Ok(client) => client,
+        Err(e) => {
+            drop(handler);
+            let _ = manager.remove(guild_id).await;
+            return Err(format!("Error connecting to {}: {}", config.backend, e).into());
+        }
+    };
+    let quality = config.resampler_quality;
+    let (sample_rate, frame_size) = (client.sample_rate(), client.frame_size());
+    // Both directions are built up front so an unsupported rate fails the join
+    let resamplers = MonoResampler::new(quality, 48000, sample_rate, 960).and_then(|to_rf| {
+        Ok((
+            to_rf,
+            MonoResampler::new(quality, sample_rate, 48000, frame_size)?,
+        ))
+    });
+    let (to_rf, from_rf) = match resamplers {
+        Ok(resamplers) => resamplers,
+        Err(e) => {
+            client.disconnect();
+            drop(handler);
+            let _ = manager.remove(guild_id).await;
+            return Err(format!("Cannot resample {} Hz audio: {}", sample_rate, e).into());
+        }
+    };
+    let cancel = CancellationToken::new();
+
+    let (status, status_task) = StatusUpdater::spawn(
+        ctx.http.clone(),
+        ctx.shard.clone(),
+        channel_id,
+        data,
+        client.stats().clone(),
+        cancel.clone(),
+    );
+
+    #[cfg(feature = "listen")]
+    let listen = Arc::new(ListenMixer::default());
+    let event_handler = BridgeEventHandler::new(
+        client.clone(),
+        guild_id,
+        ctx.http.clone(),
+        ctx.cache.clone(),
+        data,
+        data.guild_settings(guild_id).await,
+        status.clone(),
+        to_rf,
+        #[cfg(feature = "listen")]
+        listen.clone(),
+    );
+
+    handler.add_global_event(CoreEvent::SpeakingStateUpdate.into(), event_handler.clone());
+    handler.add_global_event(CoreEvent::VoiceTick.into(), event_handler.clone());
+    handler.add_global_event(CoreEvent::ClientDisconnect.into(), event_handler.clone());
+    handler.add_global_event(
+        CoreEvent::DriverDisconnect.into(),
+        Rejoin {
+            ctx: ctx.clone(),
+            data: data.clone(),
+            channel_id,
+        },
+    );
+
+    // 100ms of buffering
+    let (audio_receiver, mut audio_sender) = tokio::io::simplex(7680 * 5);
+    let stats = client.stats().clone();
+    stats.set_buffer_capacity(7680 * 5);
+
+    let audio_stream = AsyncAdapterStream::new(
+        Box::new(AsyncReadOnlySource::new(Box::new(CountingReader::new(
+            audio_receiver,
+            stats.clone(),
+        )))),
+        7680 * 5,
+    );
+    let adapter = RawAdapter::new(audio_stream, 48000, 2);
+    let _ = handler.play_input(adapter.into());
+
+    info!(
+        "Connected to voice channel {} with id {}",
+        channel_name(ctx, channel_id).await,
+        channel_id.get()
+    );
+
+    // Release the call, the announcement played below needs it
+    drop(handler);
+    let rf_panning = config.rf_panning;
+    let radioid = data.radioid.clone();
+    let lastheard = data.lastheard.clone();
+    let mut bridge = Bridge {
+        client: client.clone(),
+        #[cfg(feature = "listen")]
+        listen: listen.clone(),
+        handler: event_handler,
+        cancel: cancel.clone(),
+        tasks: vec![status_task],
+    };
+    #[cfg(feature = "listen")]
+    bridge
+        .tasks
+        .push(tokio::spawn(listen.clone().run(cancel.clone())));
+    #[cfg(feature = "icecast")]
+    if let Some(url) = &config.icecast_url {
+        bridge.tasks.push(tokio::spawn(icecast::run(
+            icecast::mount_url(url, guild_id.get()),
+            config.icecast_name.clone(),
+            listen.clone(),
+            stats.clone(),
+            cancel.clone(),
+        )));
+    }
+    let receiver_task = tokio::spawn(async move {
+        // Every RF source gets its own resampler and stereo position, and the
+        // sources are mixed into one 48kHz stream every frame
+        let mut from_rf = Some(from_rf);
+        let mut sources: HashMap<SocketAddr, RfSource> = HashMap::new();
+        let mut panner = Panner::new();
+        let mut mixer = Mixer::new(MIXER_CAPACITY);
+        let mut ticker = tokio::time::interval(Duration::from_millis(FRAME_MS as u64));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // Talker metadata announced by a source, and its transmission in progress
+        let mut talkers: HashMap<SocketAddr, Talker> = HashMap::new();
+        let mut transmissions: HashMap<SocketAddr, Transmission> = HashMap::new();
+
+        loop {
+            // Sources whose transmission is over
+            let mut ended = Vec::new();
+            tokio::select! {
+                packet = client.recv() => {
+                    let Some((packet, source)) = packet else {
+                        break;
+                    };
+                    match packet {
+                        VoicePacket::Audio(audio) => {
+                            transmissions.entry(source).or_insert_with(|| {
+                                let info = talkers.remove(&source).unwrap_or_default();
+                                let talker = match &info.name {
+                                    Some(name) if info.callsign.is_empty() => name.clone(),
+                                    Some(name) => format!("{} ({})", info.callsign, name),
+                                    None => info.callsign.clone(),
+                                };
+                                status.set_rf(Some(talker));
+                                Transmission {
+                                    callsign: info.callsign,
+                                    user_name: info.name,
+                                    dmr_id: info.dmr_id,
+                                    talkgroup: info.talkgroup,
+                                    rf_source: Some(source),
+                                    ..Transmission::start(guild_id.get(), Direction::FromRf)
+                                }
+                            });
+                            // Convert from i16 to f64
+                            let audio_vec: Vec<_> =
+                                audio.into_iter().map(|x| x as f64 / 32768.0).collect();
+                            let peak = audio_vec.iter().fold(0.0f64, |a, x| a.max(x.abs()));
+                            stats.audio_level(Direction::FromRf, peak as f32);
+                            let rf = match sources.entry(source) {
+                                Entry::Occupied(x) => x.into_mut(),
+                                Entry::Vacant(x) => {
+                                    let resampler = match from_rf.take() {
+                                        Some(resampler) => resampler,
+                                        None => match MonoResampler::new(
+                                            quality,
+                                            sample_rate,
+                                            48000,
+                                            frame_size,
+                                        ) {
+                                            Ok(resampler) => resampler,
+                                            Err(e) => {
+                                                warn!("Failed to create a resampler: {}", e);
+                                                continue;
+                                            }
+                                        },
+                                    };
+                                    let gains = if rf_panning {
+                                        panner.gains(source)
+                                    } else {
+                                        (1.0, 1.0)
+                                    };
+                                    x.insert(RfSource {
+                                        resampler,
+                                        gains,
+                                        last_heard: Instant::now(),
+                                    })
+                                }
+                            };
+                            rf.last_heard = Instant::now();
+                            // Resample to 48kHz
+                            let started = Instant::now();
+                            let resampled = rf.resampler.process(audio_vec);
+                            stats.resampled(started.elapsed());
+                            let (left, right) = rf.gains;
+                            mixer.push(
+                                source,
+                                resampled
+                                    .into_iter()
+                                    .flatten()
+                                    .map(|x| x as f32)
+                                    .flat_map(|x| [x * left, x * right]), // Mono to stereo
+                            );
+                        }
+                        VoicePacket::Start(talker) => {
+                            if let Some(mut info) = talker {
+                                // The DMR ID database knows the callsign and name
+                                // better than the peer, they show in the status
+                                // and the last heard log
+                                let record = info
+                                    .dmr_id
+                                    .and_then(|x| radioid.read().unwrap().by_dmr_id(x));
+                                if let Some(record) = record {
+                                    info.callsign = record.callsign.clone();
+                                    if !record.name.is_empty() {
+                                        info.name = Some(record.name.clone());
+                                    }
+                                }
+                                info!(
+                                    bridge = guild_id.get(),
+                                    direction = Direction::FromRf.key(),
+                                    callsign = info.callsign.as_str(),
+                                    dmr_id = info.dmr_id;
+                                    "RF {} ({}) started transmitting",
+                                    info.callsign,
+                                    info.name.as_deref().unwrap_or("unknown")
+                                );
+                                talkers.insert(source, info);
+                            }
+                        }
+                        VoicePacket::End => ended.push(source),
+                    }
+                }
+                _ = ticker.tick() => {
+                    // A source gone quiet lost its End, time its transmission out
+                    ended.extend(
+                        sources
+                            .iter()
+                            .filter(|(_, x)| x.last_heard.elapsed() >= RF_SOURCE_TIMEOUT)
+                            .map(|(&source, _)| source),
+                    );
+                    if let Some(frame) = mixer.mix(STEREO_FRAME) {
+                        #[cfg(feature = "listen")]
+                        {
+                            let mono: Vec<_> =
+                                frame.chunks_exact(2).map(|x| (x[0] + x[1]) / 2.0).collect();
+                            listen.push(Direction::FromRf, &mono);
+                        }
+                        let audio_data: Vec<_> =
+                            frame.into_iter().flat_map(|x| x.to_le_bytes()).collect();
+                        if let Ok(len) = audio_sender.write(&audio_data).await {
+                            stats.buffer_written(len);
+                        }
+                    }
+                }
+                _ = cancel.cancelled() => break,
+            }
+
+            for source in ended {
+                talkers.remove(&source);
+                if sources.remove(&source).is_some() {
+                    panner.remove(&source);
+                    mixer.end(&source);
+                }
+                if let Some(mut transmission) = transmissions.remove(&source) {
+                    if transmissions.is_empty() {
+                        status.set_rf(None);
+                    }
+                    transmission.end = Utc::now();
+                    lastheard.record(transmission);
+                }
+            }
+        }
+
+        // Log the transmissions cut short by leaving
+        for (_, mut transmission) in transmissions {
+            transmission.end = Utc::now();
+            lastheard.record(transmission);
+        }
+    });
+
+    bridge.tasks.push(receiver_task);
+    data.bridges.lock().await.insert(guild_id.get(), bridge);
+    control_op::update_presence(ctx, data, guild_id, true).await;
+    check_idle(ctx, data, guild_id).await;
+    Ok(())
+}
+
+/// Number of humans in the bot's voice channel, None when not in a voice channel
+fn humans_in_channel(ctx: &Context, guild_id: GuildId) -> Option<usize> {
+    let guild = guild_id.to_guild_cached(&ctx.cache)?;
+    let bot_id = ctx.cache.current_user().id;
+    let channel_id = guild.voice_states.get(&bot_id)?.channel_id?;
+    let humans = guild
+        .voice_states
+        .values()
+        .filter(|x| x.channel_id == Some(channel_id))
+        .filter(|x| {
+            let is_bot = x
+                .member
+                .as_ref()
+                .map(|member| member.user.bot)
+                .or_else(|| ctx.cache.user(x.user_id).map(|user| user.bot));
+            !is_bot.unwrap_or(false)
+        })
+        .count();
+    Some(humans)
+}
+
+/// Apply a change of a member's roles, nickname or registered callsign to the
+/// bridge of their guild
+pub async fn refresh_member(data: &Data, member: &Member) {
+    let bridges = data.bridges.lock().await;
+    let handler = bridges
+        .get(&member.guild_id.get())
+        .map(|x| x.handler.clone());
+    drop(bridges);
+    if let Some(handler) = handler {
+        handler.refresh_member(member).await;
+    }
+}
+
+/// Leave after `AUTO_LEAVE_SECS` once no humans remain in the bot's channel
+pub async fn check_idle(ctx: &Context, data: &Data, guild_id: GuildId) {
+    let Some(idle) = data.config.auto_leave else {
+        return;
+    };
+    // Any change of occupancy cancels the previous timer
+    let generation = data.guild_settings(guild_id).await.next_idle_generation();
+    if humans_in_channel(ctx, guild_id) != Some(0) {
+        return;
+    }
+    info!(
+        "Voice channel in guild {} is empty, leaving in {}s",
+        guild_id,
+        idle.as_secs()
+    );
+
+    let (ctx, data) = (ctx.clone(), data.clone());
+    tokio::spawn(async move {
+        tokio::time::sleep(idle).await;
+        let settings = data.guild_settings(guild_id).await;
+        if settings.idle_generation() != generation || humans_in_channel(&ctx, guild_id) != Some(0)
+        {
+            return;
+        }
+        info!("Leaving the empty voice channel in guild {}", guild_id);
+        if let Err(e) = leave(&ctx, &data, guild_id).await {
+            warn!("Failed to leave in guild {}: {}", guild_id, e);
+        }
+    });
+}
+
+/// Join a channel from `AUTO_JOIN` when someone enters it and the guild is not bridged
+pub async fn join_on_enter(ctx: &Context, data: &Data, voice_state: &VoiceState) {
+    let (Some(guild_id), Some(channel_id)) = (voice_state.guild_id, voice_state.channel_id) else {
+        return;
+    };
+    if !data.config.auto_join_on_enter
+        || !data.config.auto_join.contains(&(guild_id, channel_id))
+        || voice_state.user_id == ctx.cache.current_user().id
+        || data.bridges.lock().await.contains_key(&guild_id.get())
+    {
+        return;
+    }
+    info!(
+        "{} entered {} in guild {}, joining",
+        voice_state.user_id, channel_id, guild_id
+    );
+    tokio::spawn(join_with_retry(
+        ctx.clone(),
+        data.clone(),
+        guild_id,
+        channel_id,
+    ));
+}
+
+/// Leave the voice channel of a guild, returns the channel left
+pub async fn leave(
+    ctx: &Context,
+    data: &Data,
+    guild_id: GuildId,
+) -> Result<Option<ChannelId>, Error> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    // Also stops a pending rejoin
+    data.rejoining.lock().unwrap().remove(&guild_id.get());
+    let bridge = data.bridges.lock().await.remove(&guild_id.get());
+    let shutdown = match bridge {
+        Some(bridge) => bridge.shutdown().await,
+        None => Ok(()),
+    };
+    let Some(call) = manager.get(guild_id) else {
+        shutdown?;
+        return Ok(None);
+    };
+    let channel_id = {
+        let mut call = call.lock().await;
+        call.stop();
+        call.current_channel().map(|x| ChannelId::new(x.0.get()))
+    };
+
+    manager.remove(guild_id).await?;
+    shutdown?;
+
+    if let Some(channel_id) = channel_id {
+        info!(
+            "Disconnected from voice channel {} with id {}",
+            channel_name(ctx, channel_id).await,
+            channel_id.get()
+        );
+    }
+    Ok(channel_id)
+}
+
+/// Leave every bridge, unkeying RF and logging the transmissions in progress
+pub async fn shutdown(ctx: &Context, data: &Data) {
+    // Stop the joins retried in the background first
+    data.rejoining.lock().unwrap().clear();
+    let guilds: Vec<_> = data.bridges.lock().await.keys().copied().collect();
+    for guild_id in guilds {
+        let guild_id = GuildId::new(guild_id);
+        if let Err(e) = leave(ctx, data, guild_id).await {
+            warn!("Failed to leave cleanly in guild {}: {}", guild_id, e);
+        }
+    }
+    // The transmissions cut short by leaving are still being written
+    let lastheard = data.lastheard.clone();
+    let _ = tokio::task::spawn_blocking(move || lastheard.flush()).await;
+}
+
+/// Play an audio file in the voice channel of a guild
+pub async fn announce(ctx: &Context, guild_id: GuildId, path: PathBuf) -> Result<(), Error> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let call = manager
+        .get(guild_id)
+        .ok_or("No voice connection in this guild")?;
+    let _ = call.lock().await.play_input(File::new(path).into());
+    Ok(())
+}
+
+/// First delay between two rejoin attempts, doubled after every failure
+const REJOIN_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const REJOIN_BACKOFF_MAX: Duration = Duration::from_secs(300);
+
+/// Join a voice channel, retrying with exponential backoff until it works or
+/// the bridge is left with `/leave`
+pub async fn join_with_retry(ctx: Context, data: Data, guild_id: GuildId, channel_id: ChannelId) {
+    if !data.rejoining.lock().unwrap().insert(guild_id.get()) {
+        return;
+    }
+    let mut backoff = REJOIN_BACKOFF_MIN;
+    while data.rejoining.lock().unwrap().contains(&guild_id.get()) {
+        match join(&ctx, &data, guild_id, channel_id).await {
+            Ok(()) => break,
+            Err(e) => {
+                warn!(
+                    "Failed to join {} in guild {}, retrying in {}s: {}",
+                    channel_id,
+                    guild_id,
+                    backoff.as_secs(),
+                    e
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(REJOIN_BACKOFF_MAX);
+            }
+        }
+    }
+    data.rejoining.lock().unwrap().remove(&guild_id.get());
+}
+
+/// Join the channels configured in `AUTO_JOIN`, returns once each was tried
+/// once, failed joins are retried in the background
+pub async fn auto_join(ctx: &Context, data: &Data) {
+    for &(guild_id, channel_id) in &data.config.auto_join {
+        info!("Auto joining {} in guild {}", channel_id, guild_id);
+        if let Err(e) = join(ctx, data, guild_id, channel_id).await {
+            warn!("Failed to join {} in guild {}: {}", channel_id, guild_id, e);
+            tokio::spawn(join_with_retry(
+                ctx.clone(),
+                data.clone(),
+                guild_id,
+                channel_id,
+            ));
+        }
+    }
+}
+
+/// Rebuilds the bridge when the voice connection drops without being asked to
+struct Rejoin {
+    ctx: Context,
+    data: Data,
+    channel_id: ChannelId,
+}
+
+#[async_trait]
+impl VoiceEventHandler for Rejoin {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::DriverDisconnect(disconnect) = ctx else {
+            return None;
+        };
+        // No reason means we left on purpose
+        let reason = disconnect.reason?;
+        let guild_id = GuildId::new(disconnect.guild_id.0.get());
+        warn!(
+            "Voice connection in guild {} lost: {:?} {:?}, rejoining",
+            guild_id, disconnect.kind, reason
+        );
+
+        let (ctx, data, channel_id) = (self.ctx.clone(), self.data.clone(), self.channel_id);
+        tokio::spawn(async move {
+            if let Err(e) = leave(&ctx, &data, guild_id).await {
+                warn!(
+                    "Failed to tear down the bridge in guild {}: {}",
+                    guild_id, e
+                );
+            }
+            join_with_retry(ctx, data, guild_id, channel_id).await;
+        });
+        Some(Event::Cancel)
+    }
+}
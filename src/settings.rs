@@ -0,0 +1,128 @@
+use serenity::model::id::UserId;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+/// Audio settings moderators can apply to a single Discord user
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UserSettings {
+    /// Gain applied to the user's audio before it is sent to RF
+    pub volume: f64,
+    /// The user can still talk in Discord but never reaches RF
+    pub rf_muted: bool,
+}
+
+impl Default for UserSettings {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            rf_muted: false,
+        }
+    }
+}
+
+/// Per guild settings, shared between the slash commands and the bridge
+#[derive(Default)]
+pub struct GuildSettings {
+    users: Mutex<HashMap<UserId, UserSettings>>,
+    /// Users that were already told they are listen-only
+    listen_only_notified: Mutex<HashSet<UserId>>,
+
+    /// Last known control operator status of members
+    control_ops: Mutex<HashMap<UserId, bool>>,
+    /// A control operator is in the bridged voice channel
+    control_op_present: AtomicBool,
+
+    /// Bumped whenever the bot's voice channel may have emptied or filled
+    idle_generation: AtomicU64,
+
+    /// USRP sample rate picked with `/join`, overriding `USRP_SAMPLE_RATE`
+    usrp_sample_rate: Mutex<Option<usize>>,
+}
+
+impl GuildSettings {
+    pub fn user(&self, user_id: UserId) -> UserSettings {
+        self.users
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn set_volume(&self, user_id: UserId, volume: f64) {
+        self.users
+            .lock()
+            .unwrap()
+            .entry(user_id)
+            .or_default()
+            .volume = volume;
+    }
+
+    /// Toggle the RF mute of a user, returns whether the user is now muted
+    pub fn toggle_rf_mute(&self, user_id: UserId) -> bool {
+        let mut users = self.users.lock().unwrap();
+        let settings = users.entry(user_id).or_default();
+        settings.rf_muted = !settings.rf_muted;
+        settings.rf_muted
+    }
+
+    /// Returns true the first time it is called for a user
+    pub fn first_listen_only_notice(&self, user_id: UserId) -> bool {
+        self.listen_only_notified.lock().unwrap().insert(user_id)
+    }
+
+    pub fn set_control_op(&self, user_id: UserId, is_control_op: bool) {
+        self.control_ops
+            .lock()
+            .unwrap()
+            .insert(user_id, is_control_op);
+    }
+
+    /// None if the member's roles were never seen
+    pub fn is_control_op(&self, user_id: UserId) -> Option<bool> {
+        self.control_ops.lock().unwrap().get(&user_id).copied()
+    }
+
+    /// Members known to hold the control operator role
+    pub fn control_ops(&self) -> Vec<UserId> {
+        self.control_ops
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, &is_control_op)| is_control_op)
+            .map(|(&user_id, _)| user_id)
+            .collect()
+    }
+
+    pub fn control_op_present(&self) -> bool {
+        self.control_op_present.load(Ordering::Relaxed)
+    }
+
+    /// Returns the previous value
+    pub fn set_control_op_present(&self, present: bool) -> bool {
+        self.control_op_present.swap(present, Ordering::Relaxed)
+    }
+
+    /// Returns the new generation
+    pub fn next_idle_generation(&self) -> u64 {
+        self.idle_generation.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn idle_generation(&self) -> u64 {
+        self.idle_generation.load(Ordering::Relaxed)
+    }
+
+    pub fn usrp_sample_rate(&self) -> Option<usize> {
+        *self.usrp_sample_rate.lock().unwrap()
+    }
+
+    /// Used by every later connection of the guild, including rejoins
+    pub fn set_usrp_sample_rate(&self, sample_rate: usize) {
+        *self.usrp_sample_rate.lock().unwrap() = Some(sample_rate);
+    }
+}
@@ -0,0 +1,292 @@
+use discord_bridge::lastheard::Direction;
+use pin_project::pin_project;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// Who is transmitting on each side of the bridge
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NowTalking {
+    /// Empty when the RF talker did not identify
+    pub rf: Option<String>,
+    pub discord: Option<String>,
+}
+
+impl NowTalking {
+    /// The side being heard and its talker, RF wins when both sides talk at once
+    pub fn current(&self) -> Option<(Direction, &str)> {
+        match (&self.rf, &self.discord) {
+            (Some(rf), _) => Some((Direction::FromRf, rf)),
+            (None, Some(discord)) => Some((Direction::ToRf, discord)),
+            (None, None) => None,
+        }
+    }
+
+    pub fn text(&self) -> Option<String> {
+        match self.current()? {
+            (Direction::FromRf, "") => Some("🔴 RF".to_string()),
+            (Direction::FromRf, rf) => Some(format!("🔴 RF: {}", rf)),
+            (Direction::ToRf, discord) => Some(format!("🎙 Discord: {}", discord)),
+        }
+    }
+}
+
+/// Last packet received from RF
+#[derive(Clone, Copy, Debug)]
+pub struct LastRx {
+    pub at: Instant,
+    pub source: SocketAddr,
+}
+
+/// Health counters of a bridge, shared between the USRP client, the
+/// receive loop and the Discord event handler
+pub struct BridgeStats {
+    pub started: Instant,
+    packets_in: AtomicU64,
+    packets_out: AtomicU64,
+    /// Malformed or unknown packets received from RF
+    packets_rejected: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    /// Discontinuities in the sequence numbers received from RF
+    sequence_gaps: AtomicU64,
+    /// Last sequence number of every RF source, each counts on its own
+    sequence_numbers: Mutex<HashMap<SocketAddr, u32>>,
+    last_rx: Mutex<Option<LastRx>>,
+
+    /// Audio samples received from and sent to RF
+    samples_in: AtomicU64,
+    samples_out: AtomicU64,
+    /// Total time spent resampling, and number of resampled chunks
+    resample_nanos: AtomicU64,
+    resample_count: AtomicU64,
+
+    /// Bytes waiting in the RF to Discord audio buffer
+    buffer_fill: AtomicUsize,
+    buffer_capacity: AtomicUsize,
+
+    talker: Mutex<NowTalking>,
+    /// Peak audio levels since the last `take_levels`, as `f32` bits
+    peak_to_rf: AtomicU32,
+    peak_from_rf: AtomicU32,
+    /// SSRC to Discord user, mirrors the event handler's map
+    users: Mutex<BTreeMap<u32, String>>,
+}
+
+impl Default for BridgeStats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            packets_in: AtomicU64::new(0),
+            packets_out: AtomicU64::new(0),
+            packets_rejected: AtomicU64::new(0),
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+            sequence_gaps: AtomicU64::new(0),
+            sequence_numbers: Mutex::new(HashMap::new()),
+            last_rx: Mutex::new(None),
+            samples_in: AtomicU64::new(0),
+            samples_out: AtomicU64::new(0),
+            resample_nanos: AtomicU64::new(0),
+            resample_count: AtomicU64::new(0),
+            buffer_fill: AtomicUsize::new(0),
+            buffer_capacity: AtomicUsize::new(0),
+            talker: Mutex::new(NowTalking::default()),
+            peak_to_rf: AtomicU32::new(0),
+            peak_from_rf: AtomicU32::new(0),
+            users: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl BridgeStats {
+    pub fn received(&self, source: SocketAddr, sequence_number: u32, bytes: usize, samples: usize) {
+        self.packets_in.fetch_add(1, Ordering::Relaxed);
+        self.bytes_in.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.samples_in.fetch_add(samples as u64, Ordering::Relaxed);
+        let last = self
+            .sequence_numbers
+            .lock()
+            .unwrap()
+            .insert(source, sequence_number);
+        if last.is_some_and(|x| x.wrapping_add(1) != sequence_number) {
+            self.sequence_gaps.fetch_add(1, Ordering::Relaxed);
+        }
+        *self.last_rx.lock().unwrap() = Some(LastRx {
+            at: Instant::now(),
+            source,
+        });
+    }
+
+    pub fn rejected(&self, bytes: usize) {
+        self.packets_rejected.fetch_add(1, Ordering::Relaxed);
+        self.bytes_in.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Count a packet once it was handed to the network
+    pub fn sent(&self, bytes: usize, samples: usize) {
+        self.packets_out.fetch_add(1, Ordering::Relaxed);
+        self.bytes_out.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.samples_out
+            .fetch_add(samples as u64, Ordering::Relaxed);
+    }
+
+    pub fn resampled(&self, elapsed: Duration) {
+        self.resample_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+        self.resample_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn packets_in(&self) -> u64 {
+        self.packets_in.load(Ordering::Relaxed)
+    }
+
+    pub fn packets_out(&self) -> u64 {
+        self.packets_out.load(Ordering::Relaxed)
+    }
+
+    pub fn packets_rejected(&self) -> u64 {
+        self.packets_rejected.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_in(&self) -> u64 {
+        self.bytes_in.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_out(&self) -> u64 {
+        self.bytes_out.load(Ordering::Relaxed)
+    }
+
+    pub fn samples_in(&self) -> u64 {
+        self.samples_in.load(Ordering::Relaxed)
+    }
+
+    pub fn samples_out(&self) -> u64 {
+        self.samples_out.load(Ordering::Relaxed)
+    }
+
+    /// Total time spent resampling and number of resampled chunks
+    pub fn resample_time(&self) -> (Duration, u64) {
+        (
+            Duration::from_nanos(self.resample_nanos.load(Ordering::Relaxed)),
+            self.resample_count.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn sequence_gaps(&self) -> u64 {
+        self.sequence_gaps.load(Ordering::Relaxed)
+    }
+
+    pub fn last_rx(&self) -> Option<LastRx> {
+        *self.last_rx.lock().unwrap()
+    }
+
+    pub fn set_buffer_capacity(&self, capacity: usize) {
+        self.buffer_capacity.store(capacity, Ordering::Relaxed);
+    }
+
+    pub fn buffer_written(&self, len: usize) {
+        self.buffer_fill.fetch_add(len, Ordering::Relaxed);
+    }
+
+    /// Bytes in the buffer and its capacity
+    pub fn buffer_fill(&self) -> (usize, usize) {
+        (
+            self.buffer_fill.load(Ordering::Relaxed),
+            self.buffer_capacity.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn set_talker(&self, talker: NowTalking) {
+        *self.talker.lock().unwrap() = talker;
+    }
+
+    pub fn talker(&self) -> Option<String> {
+        self.talker.lock().unwrap().text()
+    }
+
+    #[cfg(any(feature = "dashboard", feature = "icecast"))]
+    pub fn now_talking(&self) -> NowTalking {
+        self.talker.lock().unwrap().clone()
+    }
+
+    /// Record the peak level of a chunk of audio, between 0 and 1
+    pub fn audio_level(&self, direction: Direction, peak: f32) {
+        let level = match direction {
+            Direction::ToRf => &self.peak_to_rf,
+            Direction::FromRf => &self.peak_from_rf,
+        };
+        // Non-negative floats are ordered like their bits
+        level.fetch_max(peak.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Peak levels to and from RF since the previous call
+    #[cfg(feature = "dashboard")]
+    pub fn take_levels(&self) -> (f32, f32) {
+        (
+            f32::from_bits(self.peak_to_rf.swap(0, Ordering::Relaxed)),
+            f32::from_bits(self.peak_from_rf.swap(0, Ordering::Relaxed)),
+        )
+    }
+
+    pub fn set_user(&self, ssrc: u32, user: Option<String>) {
+        let mut users = self.users.lock().unwrap();
+        match user {
+            Some(user) => users.insert(ssrc, user),
+            None => users.remove(&ssrc),
+        };
+    }
+
+    pub fn users(&self) -> Vec<(u32, String)> {
+        self.users
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&ssrc, user)| (ssrc, user.clone()))
+            .collect()
+    }
+}
+
+/// Reader that takes what it reads out of the buffer fill of a bridge
+#[pin_project]
+pub struct CountingReader<R> {
+    #[pin]
+    inner: R,
+    stats: Arc<BridgeStats>,
+}
+
+impl<R> CountingReader<R> {
+    pub fn new(inner: R, stats: Arc<BridgeStats>) -> Self {
+        Self { inner, stats }
+    }
+}
+
+impl<R: AsyncRead> AsyncRead for CountingReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.project();
+        let before = buf.filled().len();
+        ready!(this.inner.poll_read(cx, buf))?;
+        let read = buf.filled().len() - before;
+        let _ = this
+            .stats
+            .buffer_fill
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| {
+                Some(x.saturating_sub(read))
+            });
+        Poll::Ready(Ok(()))
+    }
+}
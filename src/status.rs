@@ -0,0 +1,106 @@
+use log::warn;
+use poise::serenity_prelude as serenity;
+use serde_json::json;
+use serenity::all::{ActivityData, ChannelId, Http, ShardMessenger};
+use std::sync::Arc;
+use tokio::{sync::watch, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    stats::{BridgeStats, NowTalking},
+    Data,
+};
+
+/// Shows who is talking on the voice channel status and the bot's activity.
+///
+/// Updates are coalesced so Discord sees at most one per interval, the
+/// status is cleared once the last handle is dropped or `cancel` fires. The
+/// activity belongs to the whole shard, so it is only shown while a single
+/// bridge is active.
+pub struct StatusUpdater {
+    sender: watch::Sender<NowTalking>,
+    stats: Arc<BridgeStats>,
+}
+
+impl StatusUpdater {
+    pub fn spawn(
+        http: Arc<Http>,
+        shard: ShardMessenger,
+        channel_id: ChannelId,
+        data: &Data,
+        stats: Arc<BridgeStats>,
+        cancel: CancellationToken,
+    ) -> (Arc<Self>, JoinHandle<()>) {
+        let (sender, mut receiver) = watch::channel(NowTalking::default());
+        let config = &data.config;
+        let (channel_status, presence, interval) = (
+            config.status_channel,
+            config.status_presence,
+            config.status_interval,
+        );
+        let bridges = data.bridges.clone();
+        let task = tokio::spawn(async move {
+            let mut shown = None;
+            let mut shown_activity = None;
+            loop {
+                let closed = tokio::select! {
+                    changed = receiver.changed() => changed.is_err(),
+                    _ = cancel.cancelled() => true,
+                };
+                let text = if closed {
+                    None
+                } else {
+                    receiver.borrow_and_update().text()
+                };
+                if text != shown {
+                    if channel_status {
+                        let status = json!({ "status": text.clone().unwrap_or_default() });
+                        if let Err(e) = http.edit_voice_status(channel_id, &status, None).await {
+                            warn!("Failed to set status of channel {}: {:?}", channel_id, e);
+                        }
+                    }
+                    shown = text;
+                }
+                if presence {
+                    // The bridge itself is only counted once the join finished
+                    let single = bridges.lock().await.len() <= 1;
+                    let activity = shown.clone().filter(|_| single);
+                    if activity != shown_activity {
+                        shard.set_activity(activity.clone().map(ActivityData::custom));
+                        shown_activity = activity;
+                    }
+                }
+                if closed {
+                    break;
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => {}
+                    _ = cancel.cancelled() => {}
+                }
+            }
+        });
+        (Arc::new(Self { sender, stats }), task)
+    }
+
+    /// Set or clear the callsign transmitting from RF
+    pub fn set_rf(&self, callsign: Option<String>) {
+        if self.sender.send_if_modified(|x| {
+            let modified = x.rf != callsign;
+            x.rf = callsign;
+            modified
+        }) {
+            self.stats.set_talker(self.sender.borrow().clone());
+        }
+    }
+
+    /// Set or clear the callsign transmitting from Discord
+    pub fn set_discord(&self, callsign: Option<String>) {
+        if self.sender.send_if_modified(|x| {
+            let modified = x.discord != callsign;
+            x.discord = callsign;
+            modified
+        }) {
+            self.stats.set_talker(self.sender.borrow().clone());
+        }
+    }
+}
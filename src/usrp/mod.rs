@@ -1,27 +1,50 @@
-pub(crate) mod packets;
-
-use packets::USRPPacket;
+use discord_bridge::usrp_packets::{AudioPacket, EndPacket, StartPacket, TalkerInfo, USRPPacket};
+use log::debug;
+use serenity::async_trait;
 use std::io::Error;
 use std::net::SocketAddr;
 use std::sync::atomic::AtomicU32;
+use std::sync::{Arc, Mutex};
 use tokio::net::UdpSocket;
+use tokio::sync::watch;
+
+use crate::{
+    backend::{Talker, VoiceBackend, VoicePacket},
+    stats::BridgeStats,
+};
 pub struct USRPClient {
     rx: SocketAddr,
     tx: SocketAddr,
     local_addr: SocketAddr,
 
-    rx_socket: Option<UdpSocket>,
-    tx_socket: Option<UdpSocket>,
+    rx_socket: Mutex<Option<Arc<UdpSocket>>>,
+    tx_socket: Mutex<Option<Arc<UdpSocket>>>,
+    /// Set by `disconnect` to wake up a pending `recv`
+    closed: watch::Sender<bool>,
 
     sequence_number: AtomicU32,
+    sample_rate: usize,
+    stats: Arc<BridgeStats>,
 }
 
+/// Duration of a single USRP audio frame in milliseconds
+pub const FRAME_MS: usize = 20;
+
+/// Sample rates spoken by USRP peers, slin and slin16
+pub const SAMPLE_RATES: [usize; 2] = [8000, 16000];
+
 impl USRPClient {
     /// Create a new USRPClient
     ///
     /// tx: The address to send packets to
     /// rx: The address to receive packets from
-    pub fn new(rx: SocketAddr, tx: SocketAddr, local_addr: Option<SocketAddr>) -> Self {
+    /// sample_rate: 8000 for slin, 16000 for slin16 peers
+    pub fn new(
+        rx: SocketAddr,
+        tx: SocketAddr,
+        local_addr: Option<SocketAddr>,
+        sample_rate: usize,
+    ) -> Self {
         let local_addr: SocketAddr = local_addr.unwrap_or_else(|| {
             if tx.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }
                 .parse()
@@ -32,10 +55,13 @@ impl USRPClient {
             tx,
             local_addr,
 
-            rx_socket: None,
-            tx_socket: None,
+            rx_socket: Mutex::new(None),
+            tx_socket: Mutex::new(None),
+            closed: watch::Sender::new(false),
 
             sequence_number: AtomicU32::new(0),
+            sample_rate,
+            stats: Arc::default(),
         }
     }
 
@@ -44,49 +70,131 @@ impl USRPClient {
         tx_socket.connect(&self.tx).await?;
 
         let rx_socket = UdpSocket::bind(&self.rx).await?;
-        self.tx_socket = Some(tx_socket);
-        self.rx_socket = Some(rx_socket);
+        *self.tx_socket.lock().unwrap() = Some(Arc::new(tx_socket));
+        *self.rx_socket.lock().unwrap() = Some(Arc::new(rx_socket));
+        self.closed.send_replace(false);
         Ok(())
     }
 
-    pub fn disconnect(&mut self) {
-        self.tx_socket = None;
-        self.rx_socket = None;
-    }
-
-    pub async fn recv(&self) -> Option<USRPPacket> {
-        if let Some(rx_socket) = &self.rx_socket {
+    /// Receive a packet along with the address of the peer that sent it
+    async fn recv_packet(&self) -> Option<(USRPPacket, SocketAddr)> {
+        let rx_socket = self.rx_socket.lock().unwrap().clone();
+        if let Some(rx_socket) = rx_socket {
             let mut buffer = [0; 1024];
-            let recv = rx_socket.recv_from(&mut buffer).await;
-            if let Ok((size, _)) = recv {
+            let mut closed = self.closed.subscribe();
+            let recv = tokio::select! {
+                recv = rx_socket.recv_from(&mut buffer) => recv,
+                _ = closed.wait_for(|&closed| closed) => return None,
+            };
+            if let Ok((size, source)) = recv {
                 let packet = USRPPacket::from_bytes(&buffer[..size]);
-                return Some(packet);
+                match packet.sequence_number() {
+                    Some(sequence_number) => {
+                        self.stats
+                            .received(source, sequence_number, size, packet.samples())
+                    }
+                    None => self.stats.rejected(size),
+                }
+                return Some((packet, source));
             }
         }
         None
     }
 
-    pub fn get_and_increment_sequence_number(&self) -> u32 {
+    fn get_and_increment_sequence_number(&self) -> u32 {
         self.sequence_number
             .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
     }
 
-    pub async fn send(&self, packet: USRPPacket) -> Result<usize, Error> {
-        if let Some(tx_socket) = &self.tx_socket {
+    async fn send_packet(&self, packet: USRPPacket) -> Result<usize, Error> {
+        let tx_socket = self.tx_socket.lock().unwrap().clone();
+        if let Some(tx_socket) = tx_socket {
             let bytes = packet.to_bytes();
-            return tx_socket.send(&bytes).await;
+            let sent = tx_socket.send(&bytes).await?;
+            self.stats.sent(sent, packet.samples());
+            return Ok(sent);
         }
         Ok(0)
     }
 }
 
-enum VoicePacket {
-    Start(),
-    Audio(Vec<i16>),
-    End(),
+#[async_trait]
+impl VoiceBackend for USRPClient {
+    fn peer(&self) -> String {
+        self.tx.to_string()
+    }
+
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn stats(&self) -> &Arc<BridgeStats> {
+        &self.stats
+    }
+
+    async fn recv(&self) -> Option<(VoicePacket, SocketAddr)> {
+        loop {
+            let (packet, source) = self.recv_packet().await?;
+            let packet = match packet {
+                USRPPacket::Start(packet) => VoicePacket::Start(packet.info.map(Talker::from)),
+                USRPPacket::Audio(packet) => VoicePacket::Audio(packet.audio),
+                USRPPacket::End(_) => VoicePacket::End,
+                USRPPacket::Unknown(_) => {
+                    debug!("Unknown USRP packet");
+                    continue;
+                }
+            };
+            return Some((packet, source));
+        }
+    }
+
+    async fn send(&self, packet: VoicePacket) -> Result<(), Error> {
+        let sequence_number = self.get_and_increment_sequence_number();
+        let packet = match packet {
+            VoicePacket::Start(talker) => USRPPacket::Start(StartPacket {
+                sequence_number,
+                info: talker.map(TalkerInfo::from),
+            }),
+            VoicePacket::Audio(audio) => USRPPacket::Audio(AudioPacket {
+                sequence_number,
+                transmit: true,
+                audio,
+            }),
+            VoicePacket::End => USRPPacket::End(EndPacket { sequence_number }),
+        };
+        self.send_packet(packet).await.map(|_| ())
+    }
+
+    fn disconnect(&self) {
+        *self.tx_socket.lock().unwrap() = None;
+        *self.rx_socket.lock().unwrap() = None;
+        self.closed.send_replace(true);
+    }
 }
 
-trait VoiceClient {
-    async fn recv(&self) -> Result<VoicePacket, Error>;
-    async fn send(&self, packet: VoicePacket);
+impl From<TalkerInfo> for Talker {
+    fn from(info: TalkerInfo) -> Self {
+        Self {
+            callsign: info.callsign,
+            dmr_id: Some(info.dmr_id),
+            talkgroup: Some(info.talkgroup),
+            name: None,
+        }
+    }
+}
+
+/// Talkers without a callsign are announced with the default identity
+impl From<Talker> for TalkerInfo {
+    fn from(talker: Talker) -> Self {
+        let default = Self::default();
+        if talker.callsign.is_empty() {
+            return default;
+        }
+        Self {
+            dmr_id: talker.dmr_id.unwrap_or(default.dmr_id),
+            talkgroup: talker.talkgroup.unwrap_or(default.talkgroup),
+            callsign: talker.callsign,
+            ..default
+        }
+    }
 }
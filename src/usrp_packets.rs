@@ -16,6 +16,7 @@ impl USRPPacket {
         match packet_type {
             StartPacket::PACKET_TYPE => USRPPacket::Start(StartPacket {
                 sequence_number: BigEndian::read_u32(&bytes[4..8]),
+                info: TalkerInfo::from_tlv(&bytes[32..]),
             }),
             AudioPacket::PACKET_TYPE => {
                 let sequence_number = BigEndian::read_u32(&bytes[4..8]);
@@ -23,7 +24,7 @@ impl USRPPacket {
                 let audio_u8 = &bytes[32..];
                 let mut audio = vec![0; audio_u8.len() / 2];
                 LittleEndian::read_i16_into(audio_u8, audio.as_mut_slice());
-                if audio.len() > 0 {
+                if !audio.is_empty() {
                     USRPPacket::Audio(AudioPacket {
                         sequence_number,
                         transmit,
@@ -37,6 +38,23 @@ impl USRPPacket {
         }
     }
 
+    pub fn sequence_number(&self) -> Option<u32> {
+        match self {
+            USRPPacket::Start(packet) => Some(packet.sequence_number),
+            USRPPacket::Audio(packet) => Some(packet.sequence_number),
+            USRPPacket::End(packet) => Some(packet.sequence_number),
+            USRPPacket::Unknown(_) => None,
+        }
+    }
+
+    /// Number of audio samples carried
+    pub fn samples(&self) -> usize {
+        match self {
+            USRPPacket::Audio(packet) => packet.audio.len(),
+            _ => 0,
+        }
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         match self {
             USRPPacket::Start(packet) => packet.to_bytes(),
@@ -52,8 +70,75 @@ pub trait USRPPacketSerialize {
     fn to_bytes(&self) -> Vec<u8>;
 }
 
+/// Talker metadata carried in the TLV SET_INFO tag of a start packet, as used by Analog_Bridge
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TalkerInfo {
+    pub dmr_id: u32,
+    pub repeater_id: u32,
+    pub talkgroup: u32,
+    pub slot: u8,
+    pub color_code: u8,
+    pub callsign: String,
+}
+
+impl Default for TalkerInfo {
+    fn default() -> Self {
+        Self {
+            dmr_id: 2081337,
+            repeater_id: 0x0C67DE45,
+            talkgroup: 7,
+            slot: 2,
+            color_code: 0,
+            callsign: "2081337".to_string(),
+        }
+    }
+}
+
+impl TalkerInfo {
+    const TLV_TAG_SET_INFO: u8 = 0x08;
+
+    /// Parse the SET_INFO tag at the start of a packet payload
+    pub fn from_tlv(payload: &[u8]) -> Option<Self> {
+        if payload.len() < 14 || payload[0] != Self::TLV_TAG_SET_INFO {
+            return None;
+        }
+        let len = (payload[1] as usize).min(payload.len() - 2);
+        let value = &payload[2..2 + len];
+        if value.len() < 12 {
+            return None;
+        }
+        // The callsign is null terminated
+        let callsign = value[12..].split(|&c| c == 0).next().unwrap_or_default();
+        Some(Self {
+            dmr_id: BigEndian::read_u24(&value[0..3]),
+            repeater_id: BigEndian::read_u32(&value[3..7]),
+            talkgroup: BigEndian::read_u24(&value[7..10]),
+            slot: value[10],
+            color_code: value[11],
+            callsign: String::from_utf8_lossy(callsign).into_owned(),
+        })
+    }
+
+    pub fn to_tlv(&self) -> Vec<u8> {
+        let mut value = [0; 12];
+        BigEndian::write_u24(&mut value[0..3], self.dmr_id);
+        BigEndian::write_u32(&mut value[3..7], self.repeater_id);
+        BigEndian::write_u24(&mut value[7..10], self.talkgroup);
+        value[10] = self.slot;
+        value[11] = self.color_code;
+
+        let mut tlv = vec![Self::TLV_TAG_SET_INFO, 0];
+        tlv.extend(value);
+        tlv.extend(self.callsign.bytes().take(255 - 13));
+        tlv.push(0);
+        tlv[1] = (tlv.len() - 2) as u8;
+        tlv
+    }
+}
+
 pub struct StartPacket {
     pub sequence_number: u32,
+    pub info: Option<TalkerInfo>,
 }
 
 impl USRPPacketSerialize for StartPacket {
@@ -63,10 +148,9 @@ impl USRPPacketSerialize for StartPacket {
         buffer[..4].copy_from_slice(b"USRP");
         BigEndian::write_u32(&mut buffer[4..8], self.sequence_number);
         LittleEndian::write_u32(&mut buffer[20..24], Self::PACKET_TYPE);
-        buffer[32..53].copy_from_slice(&[
-            0x08, 0x14, 0x1F, 0xC2, 0x39, 0x0C, 0x67, 0xDE, 0x45, 0x00, 0x00, 0x07, 0x02, 0x00,
-            0x32, 0x30, 0x38, 0x31, 0x33, 0x33, 0x37,
-        ]);
+        let tlv = self.info.clone().unwrap_or_default().to_tlv();
+        let len = tlv.len().min(buffer.len() - 32);
+        buffer[32..32 + len].copy_from_slice(&tlv[..len]);
         Vec::from(buffer)
     }
 }
@@ -106,11 +190,11 @@ pub struct EndPacket {
 impl USRPPacketSerialize for EndPacket {
     const PACKET_TYPE: u32 = 0;
     fn to_bytes(&self) -> Vec<u8> {
-        return AudioPacket {
+        AudioPacket {
             sequence_number: self.sequence_number,
             transmit: false,
             audio: Vec::new(),
         }
-        .to_bytes();
+        .to_bytes()
     }
 }
@@ -0,0 +1,49 @@
+use discord_bridge::sdnotify::Notifier;
+use log::warn;
+use poise::serenity_prelude as serenity;
+use serenity::all::{Context, GuildId};
+use std::{sync::Arc, time::Duration};
+
+use crate::Data;
+
+/// How often the status is refreshed when systemd has no watchdog set up
+const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Whether the USRP and voice tasks of every bridge are still running,
+/// along with the number of bridges
+async fn liveness(ctx: &Context, data: &Data) -> (usize, bool) {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.");
+    let bridges = data.bridges.lock().await;
+    let alive = bridges.iter().all(|(&guild_id, bridge)| {
+        bridge.is_alive() && manager.get(GuildId::new(guild_id)).is_some()
+    });
+    (bridges.len(), alive)
+}
+
+/// Tell systemd the bot is ready, then keep its status up to date and ping
+/// the watchdog for as long as the bridges are alive
+pub async fn run(ctx: Context, data: Data, notifier: Arc<Notifier>) {
+    if let Err(e) = notifier.ready() {
+        warn!("Failed to notify systemd: {}", e);
+    }
+    // Ping twice per watchdog interval, as systemd recommends
+    let interval = notifier.watchdog().map_or(STATUS_INTERVAL, |x| x / 2);
+    let mut shown = None;
+    loop {
+        let (bridges, alive) = liveness(&ctx, &data).await;
+        if shown != Some(bridges) {
+            let _ = notifier.status(&format!("{} active bridge(s)", bridges));
+            shown = Some(bridges);
+        }
+        if notifier.watchdog().is_some() {
+            if alive {
+                let _ = notifier.watchdog_ping();
+            } else {
+                warn!("A bridge task stopped, withholding the systemd watchdog ping");
+            }
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
@@ -0,0 +1,123 @@
+use discord_bridge::audio::{downmix, ChannelMode, Mixer, Panner};
+
+#[test]
+fn downmixes_each_channel_mode() {
+    let stereo = [16384, -8192, -32768, 0, 0, 32767];
+    assert_eq!(downmix(&stereo, ChannelMode::Left), [0.5, -1.0, 0.0]);
+    assert_eq!(
+        downmix(&stereo, ChannelMode::Right),
+        [-0.25, 0.0, 32767.0 / 32768.0]
+    );
+    assert_eq!(
+        downmix(&stereo, ChannelMode::Sum),
+        [0.125, -0.5, 32767.0 / 65536.0]
+    );
+    assert_eq!(
+        downmix(&stereo, ChannelMode::Max),
+        [0.5, -1.0, 32767.0 / 32768.0]
+    );
+}
+
+#[test]
+fn downmix_drops_an_unpaired_sample() {
+    assert_eq!(downmix(&[16384, 16384, 100], ChannelMode::Sum), [0.5]);
+    assert!(downmix(&[], ChannelMode::Left).is_empty());
+}
+
+#[test]
+fn parses_channel_modes() {
+    for mode in [
+        ChannelMode::Left,
+        ChannelMode::Right,
+        ChannelMode::Sum,
+        ChannelMode::Max,
+    ] {
+        assert_eq!(mode.to_string().parse(), Ok(mode));
+    }
+    assert_eq!("L".parse(), Ok(ChannelMode::Left));
+    assert_eq!("mix".parse(), Ok(ChannelMode::Sum));
+    assert!("both".parse::<ChannelMode>().is_err());
+}
+
+#[test]
+fn pans_sources_apart_with_constant_power() {
+    let mut panner = Panner::new();
+    assert_eq!(panner.position("a"), 0.0);
+    assert_eq!(panner.position("b"), -0.6);
+    assert_eq!(panner.position("c"), 0.6);
+    // A known source keeps its position
+    assert_eq!(panner.position("b"), -0.6);
+    for source in ["a", "b", "c"] {
+        let (left, right) = panner.gains(source);
+        assert!(
+            (left * left + right * right - 1.0).abs() < 1e-6,
+            "{}",
+            source
+        );
+    }
+    let (left, right) = panner.gains("b");
+    assert!(left > right);
+    let (left, right) = panner.gains("a");
+    assert!((left - right).abs() < 1e-6);
+}
+
+#[test]
+fn reuses_the_slot_of_a_removed_source() {
+    let mut panner = Panner::new();
+    panner.position(1);
+    panner.position(2);
+    panner.position(3);
+    panner.remove(&2);
+    assert_eq!(panner.position(4), -0.6);
+    assert_eq!(panner.position(3), 0.6);
+}
+
+#[test]
+fn mixes_sources_by_adding_them_up() {
+    let mut mixer = Mixer::new(100);
+    mixer.push("a", [0.25; 8]);
+    mixer.push("b", [0.5; 8]);
+    assert_eq!(mixer.len(), 2);
+    assert_eq!(mixer.mix(4), Some(vec![0.75; 4]));
+    assert_eq!(mixer.mix(4), Some(vec![0.75; 4]));
+}
+
+#[test]
+fn clamps_the_mix() {
+    let mut mixer = Mixer::new(100);
+    mixer.push("a", [0.75, -0.75, 0.75, -0.75]);
+    mixer.push("b", [0.75, -0.75, 0.75, -0.75]);
+    assert_eq!(mixer.mix(2), Some(vec![1.0, -1.0]));
+}
+
+#[test]
+fn waits_for_a_spare_frame_before_playing() {
+    let mut mixer = Mixer::new(100);
+    mixer.push("a", [0.5; 4]);
+    assert_eq!(mixer.mix(4), None);
+    mixer.push("a", [0.5; 4]);
+    assert_eq!(mixer.mix(4), Some(vec![0.5; 4]));
+    // Keeps playing the last frame, then waits again once dry
+    assert_eq!(mixer.mix(4), Some(vec![0.5; 4]));
+    assert_eq!(mixer.mix(4), None);
+    assert_eq!(mixer.len(), 1);
+}
+
+#[test]
+fn plays_out_and_drops_an_ended_source() {
+    let mut mixer = Mixer::new(100);
+    mixer.push("a", [0.5; 6]);
+    mixer.end(&"a");
+    assert_eq!(mixer.mix(4), Some(vec![0.5; 4]));
+    assert_eq!(mixer.mix(4), Some(vec![0.5, 0.5, 0.0, 0.0]));
+    assert!(mixer.is_empty());
+    assert_eq!(mixer.mix(4), None);
+}
+
+#[test]
+fn drops_the_oldest_audio_beyond_capacity() {
+    let mut mixer = Mixer::new(4);
+    mixer.push("a", [0.1, 0.2, 0.3, 0.4, 0.5, 0.6]);
+    mixer.end(&"a");
+    assert_eq!(mixer.mix(4), Some(vec![0.3, 0.4, 0.5, 0.6]));
+}
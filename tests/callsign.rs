@@ -0,0 +1,215 @@
+use discord_bridge::callsign::{extract_callsign, itu_country, Callsign};
+
+/// (input, base, prefix, suffix, country)
+type Case = (
+    &'static str,
+    &'static str,
+    Option<&'static str>,
+    Option<&'static str>,
+    &'static str,
+);
+
+const VALID: &[Case] = &[
+    ("W1AW", "W1AW", None, None, "United States"),
+    ("w1aw", "W1AW", None, None, "United States"),
+    ("K2ABC", "K2ABC", None, None, "United States"),
+    ("N0CALL", "N0CALL", None, None, "United States"),
+    ("AA1A", "AA1A", None, None, "United States"),
+    ("KH6ABC", "KH6ABC", None, None, "United States"),
+    ("KL7XYZ", "KL7XYZ", None, None, "United States"),
+    ("VE3ABC", "VE3ABC", None, None, "Canada"),
+    ("VA7XX", "VA7XX", None, None, "Canada"),
+    ("VY2ZM", "VY2ZM", None, None, "Canada"),
+    ("XE1ABC", "XE1ABC", None, None, "Mexico"),
+    ("G4ABC", "G4ABC", None, None, "United Kingdom"),
+    ("M0ABC", "M0ABC", None, None, "United Kingdom"),
+    ("2E0ABC", "2E0ABC", None, None, "United Kingdom"),
+    ("GM3ABC", "GM3ABC", None, None, "United Kingdom"),
+    ("EI2ABC", "EI2ABC", None, None, "Ireland"),
+    ("F5ABC", "F5ABC", None, None, "France"),
+    ("DL1ABC", "DL1ABC", None, None, "Germany"),
+    ("DO7XY", "DO7XY", None, None, "Germany"),
+    ("PA3ABC", "PA3ABC", None, None, "Netherlands"),
+    ("ON4ABC", "ON4ABC", None, None, "Belgium"),
+    ("HB9ABC", "HB9ABC", None, None, "Switzerland"),
+    ("OE1ABC", "OE1ABC", None, None, "Austria"),
+    ("I2ABC", "I2ABC", None, None, "Italy"),
+    ("IK2ABC", "IK2ABC", None, None, "Italy"),
+    ("EA4ABC", "EA4ABC", None, None, "Spain"),
+    ("CT1ABC", "CT1ABC", None, None, "Portugal"),
+    ("SM5ABC", "SM5ABC", None, None, "Sweden"),
+    ("LA9ABC", "LA9ABC", None, None, "Norway"),
+    ("OH2ABC", "OH2ABC", None, None, "Finland"),
+    ("OZ1ABC", "OZ1ABC", None, None, "Denmark"),
+    ("SP5ABC", "SP5ABC", None, None, "Poland"),
+    ("OK1ABC", "OK1ABC", None, None, "Czech Republic"),
+    ("OM3ABC", "OM3ABC", None, None, "Slovakia"),
+    ("HA5ABC", "HA5ABC", None, None, "Hungary"),
+    ("YO3ABC", "YO3ABC", None, None, "Romania"),
+    ("LZ1ABC", "LZ1ABC", None, None, "Bulgaria"),
+    ("SV1ABC", "SV1ABC", None, None, "Greece"),
+    ("9A2ABC", "9A2ABC", None, None, "Croatia"),
+    ("S51ABC", "S51ABC", None, None, "Slovenia"),
+    ("YU1ABC", "YU1ABC", None, None, "Serbia"),
+    ("E74ABC", "E74ABC", None, None, "Bosnia and Herzegovina"),
+    ("UA3ABC", "UA3ABC", None, None, "Russia"),
+    ("RA9ABC", "RA9ABC", None, None, "Russia"),
+    ("UR5ABC", "UR5ABC", None, None, "Ukraine"),
+    ("EW1ABC", "EW1ABC", None, None, "Belarus"),
+    ("YL2ABC", "YL2ABC", None, None, "Latvia"),
+    ("LY1ABC", "LY1ABC", None, None, "Lithuania"),
+    ("ES1ABC", "ES1ABC", None, None, "Estonia"),
+    ("TA1ABC", "TA1ABC", None, None, "Turkey"),
+    ("4X1AB", "4X1AB", None, None, "Israel"),
+    ("A61AB", "A61AB", None, None, "United Arab Emirates"),
+    ("A71AB", "A71AB", None, None, "Qatar"),
+    ("HZ1AB", "HZ1AB", None, None, "Saudi Arabia"),
+    ("VU2ABC", "VU2ABC", None, None, "India"),
+    ("AP2ABC", "AP2ABC", None, None, "Pakistan"),
+    ("JA1ABC", "JA1ABC", None, None, "Japan"),
+    ("7K1ABC", "7K1ABC", None, None, "Japan"),
+    ("HL1ABC", "HL1ABC", None, None, "South Korea"),
+    ("DS2ABC", "DS2ABC", None, None, "South Korea"),
+    ("BG1ABC", "BG1ABC", None, None, "China"),
+    ("VR2XX", "VR2XX", None, None, "Hong Kong"),
+    ("9V1AB", "9V1AB", None, None, "Singapore"),
+    ("9M2AB", "9M2AB", None, None, "Malaysia"),
+    ("HS0ZAB", "HS0ZAB", None, None, "Thailand"),
+    ("YB0ABC", "YB0ABC", None, None, "Indonesia"),
+    ("DU1ABC", "DU1ABC", None, None, "Philippines"),
+    ("VK2ABC", "VK2ABC", None, None, "Australia"),
+    ("ZL1ABC", "ZL1ABC", None, None, "New Zealand"),
+    ("3D2AB", "3D2AB", None, None, "Fiji"),
+    ("3DA0AB", "3DA0AB", None, None, "Eswatini"),
+    ("T32AB", "T32AB", None, None, "Kiribati"),
+    ("ZS6ABC", "ZS6ABC", None, None, "South Africa"),
+    ("5Z4AB", "5Z4AB", None, None, "Kenya"),
+    ("SU1AB", "SU1AB", None, None, "Egypt"),
+    ("ST2AB", "ST2AB", None, None, "Sudan"),
+    ("CN8AB", "CN8AB", None, None, "Morocco"),
+    ("PY2ABC", "PY2ABC", None, None, "Brazil"),
+    ("LU1ABC", "LU1ABC", None, None, "Argentina"),
+    ("CE3ABC", "CE3ABC", None, None, "Chile"),
+    ("CX1AB", "CX1AB", None, None, "Uruguay"),
+    ("HK3AB", "HK3AB", None, None, "Colombia"),
+    ("YV5ABC", "YV5ABC", None, None, "Venezuela"),
+    ("OA4AB", "OA4AB", None, None, "Peru"),
+    ("CO2AB", "CO2AB", None, None, "Cuba"),
+    ("4U1UN", "4U1UN", None, None, "United Nations"),
+    ("C31AB", "C31AB", None, None, "Andorra"),
+    ("W1AW/P", "W1AW", None, Some("P"), "United States"),
+    ("VK2ABC/M", "VK2ABC", None, Some("M"), "Australia"),
+    ("G4ABC/MM", "G4ABC", None, Some("MM"), "United Kingdom"),
+    ("K2ABC/AM", "K2ABC", None, Some("AM"), "United States"),
+    ("DL1ABC/QRP", "DL1ABC", None, Some("QRP"), "Germany"),
+    ("W1AW/4", "W1AW", None, Some("4"), "United States"),
+    ("w1aw/p", "W1AW", None, Some("P"), "United States"),
+    ("DL/W1AW", "W1AW", Some("DL"), None, "Germany"),
+    ("VE3/W1AW", "W1AW", Some("VE3"), None, "Canada"),
+    ("M/W1AW", "W1AW", Some("M"), None, "United Kingdom"),
+    ("W1AW/VE3", "W1AW", Some("VE3"), None, "Canada"),
+    ("KH6/W1AW", "W1AW", Some("KH6"), None, "United States"),
+    ("DL/W1AW/P", "W1AW", Some("DL"), Some("P"), "Germany"),
+    ("F/G4ABC/M", "G4ABC", Some("F"), Some("M"), "France"),
+    (
+        "EA8/DL1ABC/QRP",
+        "DL1ABC",
+        Some("EA8"),
+        Some("QRP"),
+        "Spain",
+    ),
+];
+
+const INVALID: &[&str] = &[
+    "",
+    "NET",
+    "HAM",
+    "ABC",
+    "W1",
+    "1AW",
+    "W1AW1",
+    "W1ABCDE",
+    "1234",
+    "C3PO",
+    "R2D2",
+    "Q1ABC",
+    "QA1ABC",
+    "00ABC",
+    "W1AW/",
+    "/W1AW",
+    "W1AW/XYZZY",
+    "W1AW/P/M",
+    "DL/VE3/W1AW",
+    "W1-AW",
+    "W1AW/ABCD",
+    "W1AW/XYZ",
+    "KH66/W1AW",
+];
+
+#[test]
+fn parses_valid_callsigns() {
+    for &(input, base, prefix, suffix, country) in VALID {
+        let callsign = Callsign::parse(input).unwrap_or_else(|| panic!("{} should parse", input));
+        assert_eq!(callsign.base, base, "base of {}", input);
+        assert_eq!(callsign.prefix.as_deref(), prefix, "prefix of {}", input);
+        assert_eq!(callsign.suffix.as_deref(), suffix, "suffix of {}", input);
+        assert_eq!(callsign.country, country, "country of {}", input);
+    }
+}
+
+#[test]
+fn rejects_invalid_callsigns() {
+    for &input in INVALID {
+        assert_eq!(Callsign::parse(input), None, "{} should not parse", input);
+    }
+}
+
+#[test]
+fn displays_prefix_first() {
+    assert_eq!(
+        Callsign::parse("w1aw/ve3/p").unwrap().to_string(),
+        "VE3/W1AW/P"
+    );
+    assert_eq!(Callsign::parse("DL/W1AW").unwrap().to_string(), "DL/W1AW");
+    assert_eq!(Callsign::parse("W1AW").unwrap().to_string(), "W1AW");
+}
+
+#[test]
+fn extracts_callsign_from_nicknames() {
+    let cases = [
+        ("John W1AW", Some("W1AW")),
+        ("W1AW John", Some("W1AW")),
+        ("john | vk2abc/m", Some("VK2ABC/M")),
+        ("[NET] Control K2ABC", Some("K2ABC")),
+        ("Alice (DL/W1AW/P)", Some("DL/W1AW/P")),
+        ("w1aw", Some("W1AW")),
+        ("bob - w1aw", Some("W1AW")),
+        ("bob w1aw", None),
+        ("w8ing for u", None),
+        ("b4u go", None),
+        ("g2g bye", None),
+        ("Bob W1AW/ABCD", None),
+        ("HAM NET Bob", None),
+        ("Bob", None),
+        ("R2D2 fan", None),
+        ("", None),
+    ];
+    for (nick, expected) in cases {
+        assert_eq!(
+            extract_callsign(nick).map(|x| x.to_string()).as_deref(),
+            expected,
+            "nick {:?}",
+            nick
+        );
+    }
+}
+
+#[test]
+fn looks_up_itu_series() {
+    assert_eq!(itu_country("K"), Some("United States"));
+    assert_eq!(itu_country("3DA"), Some("Eswatini"));
+    assert_eq!(itu_country("3DN"), Some("Fiji"));
+    assert_eq!(itu_country("EI"), Some("Ireland"));
+    assert_eq!(itu_country("Q"), None);
+    assert_eq!(itu_country("1A"), None);
+}
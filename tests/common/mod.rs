@@ -0,0 +1,24 @@
+use std::path::{Path, PathBuf};
+
+/// A file in the temporary directory unique to a test, removed when dropped
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+    /// `name` tells the tests apart, e.g. `registry-persists.json`
+    pub fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("discord-bridge-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
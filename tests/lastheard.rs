@@ -0,0 +1,104 @@
+mod common;
+
+use chrono::Duration;
+use discord_bridge::lastheard::{Direction, LastHeard, Transmission};
+use std::io::Write;
+
+use common::TempFile;
+
+fn load(log: &TempFile) -> LastHeard {
+    LastHeard::load(log.path()).unwrap()
+}
+
+fn transmission(guild_id: u64, callsign: &str, seconds: i64) -> Transmission {
+    let mut transmission = Transmission {
+        callsign: callsign.to_string(),
+        user_name: Some("Hiram".to_string()),
+        dmr_id: Some(3100001),
+        talkgroup: Some(91),
+        ..Transmission::start(guild_id, Direction::FromRf)
+    };
+    transmission.end = transmission.start + Duration::seconds(seconds);
+    transmission
+}
+
+fn callsigns(transmissions: &[Transmission]) -> Vec<&str> {
+    transmissions.iter().map(|x| x.callsign.as_str()).collect()
+}
+
+#[test]
+fn lists_recent_transmissions_newest_first() {
+    let log = TempFile::new("lastheard-recent.jsonl");
+    let lastheard = load(&log);
+    lastheard.record(transmission(1, "W1AW", 3));
+    lastheard.record(transmission(2, "G4ABC", 1));
+    lastheard.record(transmission(1, "K2ABC", 2));
+    assert_eq!(callsigns(&lastheard.recent(1, 10)), ["K2ABC", "W1AW"]);
+    assert_eq!(callsigns(&lastheard.recent(1, 1)), ["K2ABC"]);
+    assert_eq!(callsigns(&lastheard.recent(2, 10)), ["G4ABC"]);
+    assert!(lastheard.recent(3, 10).is_empty());
+}
+
+#[test]
+fn reloads_recorded_transmissions() {
+    let log = TempFile::new("lastheard-reload.jsonl");
+    let lastheard = load(&log);
+    let recorded = transmission(1, "W1AW", 5);
+    lastheard.record(recorded.clone());
+    lastheard.record(transmission(1, "K2ABC", 2));
+    lastheard.flush();
+
+    let reloaded = load(&log).recent(1, 10);
+    assert_eq!(callsigns(&reloaded), ["K2ABC", "W1AW"]);
+    let loaded = &reloaded[1];
+    assert_eq!(loaded.start, recorded.start);
+    assert_eq!(loaded.duration(), Duration::seconds(5));
+    assert_eq!(loaded.direction, Direction::FromRf);
+    assert_eq!(loaded.user_name.as_deref(), Some("Hiram"));
+    assert_eq!(loaded.dmr_id, Some(3100001));
+    assert_eq!(loaded.talkgroup, Some(91));
+}
+
+#[test]
+fn skips_malformed_lines() {
+    let log = TempFile::new("lastheard-malformed.jsonl");
+    let line = serde_json::to_string(&transmission(1, "W1AW", 1)).unwrap();
+    let mut file = std::fs::File::create(log.path()).unwrap();
+    writeln!(file, "{}", line).unwrap();
+    writeln!(file, "not json").unwrap();
+    file.write_all(b"\xff\xfe invalid utf-8\n").unwrap();
+    writeln!(file, "{}", line.replace("W1AW", "K2ABC")).unwrap();
+    drop(file);
+
+    let lastheard = load(&log);
+    assert_eq!(callsigns(&lastheard.recent(1, 10)), ["K2ABC", "W1AW"]);
+}
+
+#[test]
+fn exports_the_log_of_a_guild() {
+    let log = TempFile::new("lastheard-export.jsonl");
+    let lastheard = load(&log);
+    lastheard.record(transmission(1, "W1AW", 3));
+    lastheard.record(transmission(2, "G4ABC", 1));
+    let csv = String::from_utf8(lastheard.export_csv(1).unwrap()).unwrap();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("start,end,duration_s,direction,callsign"));
+    assert!(lines[1].contains(",3.0,FromRf,W1AW,"), "{}", lines[1]);
+}
+
+#[test]
+fn a_missing_log_is_empty() {
+    let log = TempFile::new("lastheard-missing.jsonl");
+    let lastheard = load(&log);
+    assert!(lastheard.recent(1, 10).is_empty());
+    assert_eq!(
+        lastheard
+            .export_csv(1)
+            .unwrap()
+            .iter()
+            .filter(|&&x| x == b'\n')
+            .count(),
+        1
+    );
+}
@@ -0,0 +1,135 @@
+#![cfg(feature = "mumble")]
+
+use discord_bridge::{
+    mumble_proto::{self as proto, MessageType, UserState},
+    mumble_voice::{decode, encode, read_varint, write_varint},
+};
+
+/// Boundaries of every varint length
+const VARINTS: [u64; 12] = [
+    0,
+    0x7f,
+    0x80,
+    0x3fff,
+    0x4000,
+    0x1f_ffff,
+    0x20_0000,
+    0x0fff_ffff,
+    0x1000_0000,
+    0xffff_ffff,
+    0x1_0000_0000,
+    u64::MAX,
+];
+
+/// A voice packet as the server relays it, with the session of the talker
+fn incoming(session: u32, sequence: u64, opus: &[u8], terminator: bool) -> Vec<u8> {
+    let mut packet = vec![4 << 5];
+    write_varint(&mut packet, session as u64);
+    write_varint(&mut packet, sequence);
+    let len = opus.len() as u64;
+    write_varint(&mut packet, if terminator { len | 0x2000 } else { len });
+    packet.extend_from_slice(opus);
+    packet
+}
+
+#[test]
+fn varints_round_trip() {
+    for value in VARINTS {
+        let mut out = Vec::new();
+        write_varint(&mut out, value);
+        let mut input = &out[..];
+        assert_eq!(read_varint(&mut input), Some(value), "{:#x}", value);
+        assert!(input.is_empty(), "{:#x} left bytes behind", value);
+    }
+}
+
+#[test]
+fn varints_use_the_shortest_length() {
+    let lengths = [1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 9, 9];
+    for (value, len) in VARINTS.into_iter().zip(lengths) {
+        let mut out = Vec::new();
+        write_varint(&mut out, value);
+        assert_eq!(out.len(), len, "{:#x}", value);
+    }
+}
+
+#[test]
+fn truncated_varints_are_rejected() {
+    let mut out = Vec::new();
+    write_varint(&mut out, 0x1000_0000);
+    let mut input = &out[..3];
+    assert_eq!(read_varint(&mut input), None);
+    assert_eq!(read_varint(&mut &[][..]), None);
+}
+
+#[test]
+fn encoded_voice_carries_sequence_and_length() {
+    let opus = [1, 2, 3, 4, 5];
+    for terminator in [false, true] {
+        let packet = encode(300, &opus, terminator);
+        assert_eq!(packet[0] >> 5, 4);
+        let mut rest = &packet[1..];
+        assert_eq!(read_varint(&mut rest), Some(300));
+        let len = read_varint(&mut rest).unwrap();
+        assert_eq!(len & 0x1fff, opus.len() as u64);
+        assert_eq!(len & 0x2000 != 0, terminator);
+        assert_eq!(rest, opus);
+    }
+}
+
+#[test]
+fn incoming_voice_is_decoded() {
+    let opus: Vec<u8> = (0..200).map(|x| x as u8).collect();
+    for terminator in [false, true] {
+        let packet = incoming(1234, 0x4000, &opus, terminator);
+        let voice = decode(&packet).unwrap();
+        assert_eq!(voice.session, 1234);
+        assert_eq!(voice.opus, &opus[..]);
+        assert_eq!(voice.terminator, terminator);
+    }
+}
+
+#[test]
+fn other_codecs_and_short_packets_are_ignored() {
+    let mut ping = incoming(1, 1, &[1, 2, 3], false);
+    ping[0] = 1 << 5;
+    assert!(decode(&ping).is_none());
+    let packet = incoming(1, 1, &[1, 2, 3], false);
+    assert!(decode(&packet[..packet.len() - 1]).is_none());
+    assert!(decode(&[]).is_none());
+}
+
+#[tokio::test]
+async fn messages_round_trip() {
+    let state = UserState {
+        session: Some(7),
+        actor: None,
+        name: Some("N0CALL".to_string()),
+        channel_id: Some(3),
+    };
+    let mut buffer = Vec::new();
+    proto::write(&mut buffer, MessageType::UserState, &state)
+        .await
+        .unwrap();
+    let (message_type, payload) = proto::read(&mut &buffer[..]).await.unwrap();
+    assert_eq!(message_type, MessageType::UserState);
+    assert_eq!(proto::decode::<UserState>(&payload).unwrap(), state);
+}
+
+#[tokio::test]
+async fn unknown_messages_are_skipped() {
+    // Type 11 is TextMessage, which the bridge does not read
+    let mut buffer = vec![0, 11, 0, 0, 0, 2, 0xaa, 0xbb];
+    buffer.extend(proto::frame(MessageType::Ping, &[1, 2, 3]));
+    let (message_type, payload) = proto::read(&mut &buffer[..]).await.unwrap();
+    assert_eq!(message_type, MessageType::Ping);
+    assert_eq!(payload, [1, 2, 3]);
+}
+
+#[tokio::test]
+async fn oversized_and_truncated_messages_fail() {
+    let oversized = [0, 3, 0x10, 0, 0, 0];
+    assert!(proto::read(&mut &oversized[..]).await.is_err());
+    let frame = proto::frame(MessageType::Ping, &[1, 2, 3]);
+    assert!(proto::read(&mut &frame[..frame.len() - 1]).await.is_err());
+}
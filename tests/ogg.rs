@@ -0,0 +1,126 @@
+use discord_bridge::ogg::{crc32, OggOpusWriter, PRE_SKIP};
+
+/// A page split into its header fields and payload
+struct Page<'a> {
+    header_type: u8,
+    granule: u64,
+    serial: u32,
+    sequence: u32,
+    segments: &'a [u8],
+    data: &'a [u8],
+}
+
+/// Parse the page at the start of `bytes`, checking its CRC, returns the rest
+fn parse(bytes: &[u8]) -> (Page<'_>, &[u8]) {
+    assert_eq!(&bytes[0..4], b"OggS");
+    assert_eq!(bytes[4], 0);
+    let count = bytes[26] as usize;
+    let segments = &bytes[27..27 + count];
+    let len: usize = segments.iter().map(|&x| x as usize).sum();
+    let end = 27 + count + len;
+
+    let mut page = bytes[..end].to_vec();
+    page[22..26].fill(0);
+    let crc = u32::from_le_bytes(bytes[22..26].try_into().unwrap());
+    assert_eq!(crc32(&page), crc);
+
+    let page = Page {
+        header_type: bytes[5],
+        granule: u64::from_le_bytes(bytes[6..14].try_into().unwrap()),
+        serial: u32::from_le_bytes(bytes[14..18].try_into().unwrap()),
+        sequence: u32::from_le_bytes(bytes[18..22].try_into().unwrap()),
+        segments,
+        data: &bytes[27 + count..end],
+    };
+    (page, &bytes[end..])
+}
+
+#[test]
+fn crc() {
+    assert_eq!(crc32(b""), 0);
+    // CRC-32/CKSUM without its final inversion
+    assert_eq!(crc32(b"123456789"), !0x765e_7680);
+}
+
+#[test]
+fn headers() {
+    let mut writer = OggOpusWriter::new(0x1234_5678);
+    let bytes = writer.headers(1, 8000, &["TITLE=N0CALL".to_string()]);
+
+    let (head, rest) = parse(&bytes);
+    assert_eq!(head.header_type, 0x02);
+    assert_eq!(head.granule, 0);
+    assert_eq!(head.serial, 0x1234_5678);
+    assert_eq!(head.sequence, 0);
+    assert_eq!(&head.data[0..8], b"OpusHead");
+    assert_eq!(head.data[8], 1);
+    assert_eq!(head.data[9], 1);
+    assert_eq!(u16::from_le_bytes([head.data[10], head.data[11]]), PRE_SKIP);
+    assert_eq!(
+        u32::from_le_bytes(head.data[12..16].try_into().unwrap()),
+        8000
+    );
+    assert_eq!(head.data.len(), 19);
+
+    let (tags, rest) = parse(rest);
+    assert_eq!(tags.header_type, 0);
+    assert_eq!(tags.sequence, 1);
+    assert_eq!(&tags.data[0..8], b"OpusTags");
+    let vendor = u32::from_le_bytes(tags.data[8..12].try_into().unwrap()) as usize;
+    let comments = &tags.data[12 + vendor..];
+    assert_eq!(u32::from_le_bytes(comments[0..4].try_into().unwrap()), 1);
+    assert_eq!(u32::from_le_bytes(comments[4..8].try_into().unwrap()), 12);
+    assert_eq!(&comments[8..], b"TITLE=N0CALL");
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn packets() {
+    let mut writer = OggOpusWriter::new(7);
+    writer.headers(1, 16000, &[]);
+
+    let bytes = writer.packet(&[0xaa; 10], 960);
+    let (page, _) = parse(&bytes);
+    assert_eq!(page.granule, 960);
+    assert_eq!(page.sequence, 2);
+    assert_eq!(page.segments, &[10]);
+    assert_eq!(page.data, &[0xaa; 10]);
+
+    // A packet filling whole segments ends with an empty one
+    let bytes = writer.packet(&[0x55; 255], 960);
+    let (page, _) = parse(&bytes);
+    assert_eq!(page.granule, 1920);
+    assert_eq!(page.sequence, 3);
+    assert_eq!(page.segments, &[255, 0]);
+
+    let bytes = writer.packet(&[0x11; 600], 960);
+    let (page, _) = parse(&bytes);
+    assert_eq!(page.segments, &[255, 255, 90]);
+    assert_eq!(page.data.len(), 600);
+}
+
+#[test]
+fn chain() {
+    let mut writer = OggOpusWriter::new(1);
+    writer.headers(1, 8000, &[]);
+    writer.packet(&[0xaa; 10], 960);
+
+    let bytes = writer.chain(2);
+    let (end, rest) = parse(&bytes);
+    assert_eq!(end.header_type, 0x04);
+    assert_eq!(end.granule, 960);
+    assert_eq!(end.serial, 1);
+    assert_eq!(end.sequence, 3);
+    assert!(end.segments.is_empty());
+    assert!(rest.is_empty());
+
+    // The next stream starts over with its own headers
+    let bytes = writer.headers(1, 8000, &[]);
+    let (head, _) = parse(&bytes);
+    assert_eq!(head.header_type, 0x02);
+    assert_eq!(head.serial, 2);
+    assert_eq!(head.sequence, 0);
+    let bytes = writer.packet(&[0xaa; 10], 960);
+    let (page, _) = parse(&bytes);
+    assert_eq!(page.granule, 960);
+}
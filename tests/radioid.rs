@@ -0,0 +1,80 @@
+use discord_bridge::radioid::RadioIdDatabase;
+use std::io::ErrorKind;
+
+const RADIOID_DUMP: &str = "\
+RADIO_ID,CALLSIGN,FIRST_NAME,LAST_NAME,CITY,STATE,COUNTRY
+3100001,w1aw,Hiram,Maxim,Newington,Connecticut,United States
+3100002,W1AW,Hiram,,Newington,Connecticut,United States
+2341234,G4ABC,Alice,Smith,London,England,United Kingdom
+";
+
+#[test]
+fn parses_a_radioid_dump() {
+    let database = RadioIdDatabase::from_reader(RADIOID_DUMP.as_bytes()).unwrap();
+    assert_eq!(database.len(), 3);
+
+    let record = database.by_dmr_id(3100001).unwrap();
+    assert_eq!(record.callsign, "W1AW");
+    assert_eq!(record.name, "Hiram Maxim");
+    assert_eq!(record.city, "Newington");
+    assert_eq!(record.state, "Connecticut");
+    assert_eq!(record.country, "United States");
+    // A missing last name leaves no trailing space
+    assert_eq!(database.by_dmr_id(3100002).unwrap().name, "Hiram");
+    assert_eq!(database.by_dmr_id(1), None);
+}
+
+#[test]
+fn finds_every_id_of_a_callsign() {
+    let database = RadioIdDatabase::from_reader(RADIOID_DUMP.as_bytes()).unwrap();
+    let ids: Vec<_> = database
+        .by_callsign("w1aw")
+        .iter()
+        .map(|x| x.dmr_id)
+        .collect();
+    assert_eq!(ids, [3100001, 3100002]);
+    assert!(database.by_callsign("K2ABC").is_empty());
+}
+
+#[test]
+fn detects_alternative_column_names() {
+    let csv = " Id , Callsign ,fname,Surname\n1234567, VK2ABC ,Bob,Jones\n";
+    let database = RadioIdDatabase::from_reader(csv.as_bytes()).unwrap();
+    let record = database.by_dmr_id(1234567).unwrap();
+    assert_eq!(record.callsign, "VK2ABC");
+    assert_eq!(record.name, "Bob Jones");
+    assert_eq!(record.city, "");
+}
+
+#[test]
+fn finds_columns_in_any_order() {
+    let csv = "country,callsign,dmr_id,name\nGermany,DL1ABC,2621234,Karl\n";
+    let database = RadioIdDatabase::from_reader(csv.as_bytes()).unwrap();
+    let record = database.by_dmr_id(2621234).unwrap();
+    assert_eq!(record.callsign, "DL1ABC");
+    assert_eq!(record.name, "Karl");
+    assert_eq!(record.country, "Germany");
+}
+
+#[test]
+fn skips_rows_with_a_malformed_id() {
+    let csv = "radio_id,callsign\nabc,W1AW\n,K2ABC\n3100001,N0CALL\n42\n";
+    let database = RadioIdDatabase::from_reader(csv.as_bytes()).unwrap();
+    assert_eq!(database.len(), 2);
+    assert_eq!(database.by_dmr_id(3100001).unwrap().callsign, "N0CALL");
+    // A short row still parses, with an empty callsign
+    assert_eq!(database.by_dmr_id(42).unwrap().callsign, "");
+}
+
+#[test]
+fn rejects_a_dump_without_id_or_callsign_column() {
+    for csv in [
+        "callsign,name\nW1AW,Hiram\n",
+        "radio_id,name\n3100001,Hiram\n",
+    ] {
+        let error = RadioIdDatabase::from_reader(csv.as_bytes())
+            .err()
+            .unwrap_or_else(|| panic!("{:?} should not parse", csv));
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
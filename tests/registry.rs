@@ -0,0 +1,126 @@
+mod common;
+
+use discord_bridge::{
+    callsign::Callsign,
+    registry::{CallsignRegistry, RegistryError},
+};
+use serenity::model::id::UserId;
+
+use common::TempFile;
+
+const ALICE: UserId = UserId::new(1);
+const BOB: UserId = UserId::new(2);
+
+fn load(file: &TempFile) -> CallsignRegistry {
+    CallsignRegistry::load(file.path()).unwrap()
+}
+
+fn call(text: &str) -> Callsign {
+    Callsign::parse(text).unwrap()
+}
+
+#[tokio::test]
+async fn persists_entries() {
+    let file = TempFile::new("registry-persists.json");
+    let registry = load(&file);
+    assert_eq!(registry.get(ALICE), None);
+    registry.set(ALICE, &call("w1aw/p"), false).await.unwrap();
+    registry.set_verified(ALICE, true).await.unwrap();
+
+    let entry = load(&file).get(ALICE).unwrap();
+    assert_eq!(entry.callsign, "W1AW/P");
+    assert!(entry.verified);
+    assert!(!entry.locked);
+}
+
+#[tokio::test]
+async fn a_new_callsign_needs_verifying_again() {
+    let file = TempFile::new("registry-reverify.json");
+    let registry = load(&file);
+    registry.set(ALICE, &call("W1AW"), false).await.unwrap();
+    registry.set_verified(ALICE, true).await.unwrap();
+    // Setting the same callsign again keeps the verification
+    registry.set(ALICE, &call("W1AW"), false).await.unwrap();
+    assert!(registry.get(ALICE).unwrap().verified);
+    registry.set(ALICE, &call("K2ABC"), false).await.unwrap();
+    assert!(!registry.get(ALICE).unwrap().verified);
+}
+
+#[tokio::test]
+async fn a_verified_callsign_is_taken_with_any_prefix_or_suffix() {
+    let file = TempFile::new("registry-taken.json");
+    let registry = load(&file);
+    registry.set(ALICE, &call("W1AW"), false).await.unwrap();
+    // Unverified callsigns can be claimed by several users
+    registry.set(BOB, &call("W1AW"), false).await.unwrap();
+    registry.set_verified(ALICE, true).await.unwrap();
+    for taken in ["W1AW", "W1AW/P", "DL/W1AW", "VE3/W1AW/M"] {
+        match registry.set(BOB, &call(taken), false).await {
+            Err(RegistryError::Taken(owner)) => assert_eq!(owner, ALICE, "{}", taken),
+            other => panic!("{} should be taken, got {:?}", taken, other),
+        }
+    }
+    registry.set(BOB, &call("W1AWX"), false).await.unwrap();
+    // The owner can still add a suffix
+    registry.set(ALICE, &call("W1AW/P"), false).await.unwrap();
+}
+
+#[tokio::test]
+async fn locked_entries_need_an_admin() {
+    let file = TempFile::new("registry-locked.json");
+    let registry = load(&file);
+    registry.set(ALICE, &call("W1AW"), false).await.unwrap();
+    registry.set_locked(ALICE, true).await.unwrap();
+    assert!(matches!(
+        registry.set(ALICE, &call("K2ABC"), false).await,
+        Err(RegistryError::Locked)
+    ));
+    assert!(matches!(
+        registry.clear(ALICE, false).await,
+        Err(RegistryError::Locked)
+    ));
+    registry.set(ALICE, &call("K2ABC"), true).await.unwrap();
+    let entry = registry.get(ALICE).unwrap();
+    assert_eq!(entry.callsign, "K2ABC");
+    assert!(entry.locked);
+    assert_eq!(
+        registry.clear(ALICE, true).await.unwrap().unwrap().callsign,
+        "K2ABC"
+    );
+    assert_eq!(load(&file).get(ALICE), None);
+}
+
+#[tokio::test]
+async fn updates_need_an_entry() {
+    let file = TempFile::new("registry-missing.json");
+    let registry = load(&file);
+    assert_eq!(registry.set_verified(BOB, true).await.unwrap(), None);
+    assert_eq!(registry.set_locked(BOB, true).await.unwrap(), None);
+    assert_eq!(registry.clear(BOB, false).await.unwrap(), None);
+    assert!(!file.path().exists());
+}
+
+#[tokio::test]
+async fn concurrent_changes_are_all_saved() {
+    let file = TempFile::new("registry-concurrent.json");
+    let registry = std::sync::Arc::new(load(&file));
+    let tasks: Vec<_> = (1..=20)
+        .map(|i| {
+            let registry = registry.clone();
+            tokio::spawn(async move {
+                let callsign = call(&format!("W{}AW", i % 10));
+                registry
+                    .set(UserId::new(i), &callsign, false)
+                    .await
+                    .unwrap();
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    let reloaded = load(&file);
+    for i in 1..=20 {
+        assert!(reloaded.get(UserId::new(i)).is_some(), "user {}", i);
+    }
+}
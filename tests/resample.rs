@@ -0,0 +1,103 @@
+use discord_bridge::resample::{MonoResampler, ResamplerQuality};
+
+const PRESETS: [ResamplerQuality; 3] = [
+    ResamplerQuality::Fast,
+    ResamplerQuality::Balanced,
+    ResamplerQuality::High,
+];
+
+fn tone(len: usize, rate: f64) -> Vec<f64> {
+    (0..len)
+        .map(|i| 0.5 * (2.0 * std::f64::consts::PI * 1000.0 * i as f64 / rate).sin())
+        .collect()
+}
+
+/// (from rate, to rate, chunk size) of both directions at both USRP rates
+const DIRECTIONS: [(usize, usize, usize); 4] = [
+    (48000, 8000, 960),
+    (8000, 48000, 160),
+    (48000, 16000, 960),
+    (16000, 48000, 320),
+];
+
+#[test]
+fn keeps_the_rate_ratio() {
+    for quality in PRESETS {
+        for (from, to, chunk) in DIRECTIONS {
+            let mut resampler = MonoResampler::new(quality, from, to, chunk).unwrap();
+            assert_eq!(
+                resampler.input_frames(),
+                chunk,
+                "{} {}->{}",
+                quality,
+                from,
+                to
+            );
+            // The sinc resamplers hold some output back at first, skip their delay
+            let mut lengths: Vec<_> = (0..100)
+                .map(|_| resampler.process(tone(chunk, from as f64)).unwrap().len())
+                .collect();
+            let output: usize = lengths.split_off(50).into_iter().sum();
+            let expected = 50 * chunk * to / from;
+            assert!(
+                output.abs_diff(expected) <= expected / 100,
+                "{} {}->{}: {} samples, expected {}",
+                quality,
+                from,
+                to,
+                output,
+                expected
+            );
+        }
+    }
+}
+
+#[test]
+fn passes_a_tone_through() {
+    for quality in PRESETS {
+        let mut resampler = MonoResampler::new(quality, 8000, 48000, 160).unwrap();
+        let mut output = Vec::new();
+        for _ in 0..50 {
+            output.extend(resampler.process(tone(160, 8000.0)).unwrap());
+        }
+        // Skip the filter delay, then the 0.5 amplitude tone should come out intact
+        let peak = output[output.len() / 2..]
+            .iter()
+            .fold(0.0f64, |a, x| a.max(x.abs()));
+        assert!((0.45..0.55).contains(&peak), "{}: peak {}", quality, peak);
+    }
+}
+
+#[test]
+fn rejects_chunks_of_the_wrong_size() {
+    for quality in PRESETS {
+        let mut resampler = MonoResampler::new(quality, 48000, 8000, 960).unwrap();
+        assert_eq!(resampler.process(tone(100, 48000.0)), None, "{}", quality);
+    }
+}
+
+#[test]
+fn rejects_a_zero_sample_rate() {
+    for quality in PRESETS {
+        assert!(
+            MonoResampler::new(quality, 0, 48000, 160).is_err(),
+            "{}",
+            quality
+        );
+        assert!(
+            MonoResampler::new(quality, 48000, 0, 960).is_err(),
+            "{}",
+            quality
+        );
+    }
+}
+
+#[test]
+fn parses_presets() {
+    for quality in PRESETS {
+        assert_eq!(quality.to_string().parse(), Ok(quality));
+    }
+    assert_eq!("FFT".parse(), Ok(ResamplerQuality::Fast));
+    assert_eq!("hq".parse(), Ok(ResamplerQuality::High));
+    assert!("best".parse::<ResamplerQuality>().is_err());
+}
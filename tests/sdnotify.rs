@@ -0,0 +1,95 @@
+#![cfg(unix)]
+
+use discord_bridge::sdnotify::{watchdog_interval, Notifier};
+use std::{os::unix::net::UnixDatagram, path::PathBuf, time::Duration};
+
+/// A fake systemd listening on a notify socket
+struct FakeSystemd {
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+impl FakeSystemd {
+    fn bind(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "discord-bridge-{}-{}.sock",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        Self { socket, path }
+    }
+
+    fn recv(&self) -> String {
+        let mut buffer = [0; 1024];
+        let len = self.socket.recv(&mut buffer).unwrap();
+        String::from_utf8(buffer[..len].to_vec()).unwrap()
+    }
+}
+
+impl Drop for FakeSystemd {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[test]
+fn notifications() {
+    let systemd = FakeSystemd::bind("notify");
+    let notifier = Notifier::connect(&systemd.path).unwrap();
+    assert_eq!(notifier.watchdog(), None);
+
+    notifier.ready().unwrap();
+    assert_eq!(systemd.recv(), "READY=1");
+    notifier.status("2 active bridge(s)").unwrap();
+    assert_eq!(systemd.recv(), "STATUS=2 active bridge(s)");
+    notifier.status("multi\nline").unwrap();
+    assert_eq!(systemd.recv(), "STATUS=multi line");
+    notifier.watchdog_ping().unwrap();
+    assert_eq!(systemd.recv(), "WATCHDOG=1");
+    notifier.stopping().unwrap();
+    assert_eq!(systemd.recv(), "STOPPING=1");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn abstract_socket() {
+    use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+
+    let name = format!("discord-bridge-test-{}", std::process::id());
+    let socket =
+        UnixDatagram::bind_addr(&SocketAddr::from_abstract_name(name.as_bytes()).unwrap()).unwrap();
+    let notifier = Notifier::connect(format!("@{}", name)).unwrap();
+    notifier.ready().unwrap();
+    let mut buffer = [0; 64];
+    let len = socket.recv(&mut buffer).unwrap();
+    assert_eq!(&buffer[..len], b"READY=1");
+}
+
+#[test]
+fn missing_socket() {
+    let path = std::env::temp_dir().join("discord-bridge-does-not-exist.sock");
+    assert!(Notifier::connect(path).is_err());
+}
+
+#[test]
+fn watchdog() {
+    let pid = std::process::id().to_string();
+    assert_eq!(
+        watchdog_interval(Some("30000000"), None),
+        Some(Duration::from_secs(30))
+    );
+    assert_eq!(
+        watchdog_interval(Some("500000"), Some(&pid)),
+        Some(Duration::from_millis(500))
+    );
+    // Meant for another process
+    assert_eq!(watchdog_interval(Some("30000000"), Some("1")), None);
+    assert_eq!(watchdog_interval(None, None), None);
+    assert_eq!(watchdog_interval(Some("0"), None), None);
+    assert_eq!(watchdog_interval(Some("soon"), None), None);
+}
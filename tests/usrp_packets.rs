@@ -0,0 +1,147 @@
+use discord_bridge::usrp_packets::{
+    AudioPacket, EndPacket, StartPacket, TalkerInfo, USRPPacket, USRPPacketSerialize,
+};
+
+/// SET_INFO tag the original bridge sent in every start packet
+const BASELINE_IDENTITY: [u8; 21] = [
+    0x08, 0x14, 0x1F, 0xC2, 0x39, 0x0C, 0x67, 0xDE, 0x45, 0x00, 0x00, 0x07, 0x02, 0x00, 0x32, 0x30,
+    0x38, 0x31, 0x33, 0x33, 0x37,
+];
+
+/// A start packet as sent by the original bridge
+fn baseline_start_packet(sequence_number: u32) -> Vec<u8> {
+    let mut buffer = vec![0; 352];
+    buffer[..4].copy_from_slice(b"USRP");
+    buffer[4..8].copy_from_slice(&sequence_number.to_be_bytes());
+    buffer[20..24].copy_from_slice(&2u32.to_le_bytes());
+    buffer[32..53].copy_from_slice(&BASELINE_IDENTITY);
+    buffer
+}
+
+#[test]
+fn default_identity_matches_the_baseline() {
+    // The original bridge left the null ending the callsign to the zeroed packet
+    let mut tlv = BASELINE_IDENTITY.to_vec();
+    tlv.push(0);
+    assert_eq!(TalkerInfo::default().to_tlv(), tlv);
+    assert_eq!(
+        TalkerInfo::from_tlv(&BASELINE_IDENTITY),
+        Some(TalkerInfo::default())
+    );
+}
+
+#[test]
+fn start_packet_matches_the_baseline() {
+    let packet = StartPacket {
+        sequence_number: 1234,
+        info: None,
+    };
+    assert_eq!(packet.to_bytes(), baseline_start_packet(1234));
+    let packet = StartPacket {
+        sequence_number: 1234,
+        info: Some(TalkerInfo::default()),
+    };
+    assert_eq!(packet.to_bytes(), baseline_start_packet(1234));
+}
+
+#[test]
+fn round_trips_talker_info() {
+    let info = TalkerInfo {
+        dmr_id: 3100001,
+        repeater_id: 0x01020304,
+        talkgroup: 91,
+        slot: 1,
+        color_code: 7,
+        callsign: "W1AW".to_string(),
+    };
+    let tlv = info.to_tlv();
+    assert_eq!(tlv[0], 0x08);
+    assert_eq!(tlv[1] as usize, tlv.len() - 2);
+    assert_eq!(&tlv[2..5], &3100001u32.to_be_bytes()[1..]);
+    assert_eq!(&tlv[14..], b"W1AW\0");
+    assert_eq!(TalkerInfo::from_tlv(&tlv), Some(info));
+}
+
+#[test]
+fn truncates_a_long_callsign() {
+    let info = TalkerInfo {
+        callsign: "X".repeat(300),
+        ..TalkerInfo::default()
+    };
+    let tlv = info.to_tlv();
+    assert_eq!(tlv.len(), 257);
+    assert_eq!(tlv[1], 255);
+    let parsed = TalkerInfo::from_tlv(&tlv).unwrap();
+    assert_eq!(parsed.callsign.len(), 255 - 13);
+}
+
+#[test]
+fn rejects_other_tags_and_short_payloads() {
+    let mut tlv = TalkerInfo::default().to_tlv();
+    assert_eq!(TalkerInfo::from_tlv(&tlv[..13]), None);
+    tlv[0] = 0x09;
+    assert_eq!(TalkerInfo::from_tlv(&tlv), None);
+    assert_eq!(TalkerInfo::from_tlv(&[0; 320]), None);
+}
+
+#[test]
+fn parses_a_start_packet_with_talker_info() {
+    let info = TalkerInfo {
+        callsign: "G4ABC".to_string(),
+        ..TalkerInfo::default()
+    };
+    let mut bytes = vec![0; 352];
+    bytes[..4].copy_from_slice(b"USRP");
+    bytes[4..8].copy_from_slice(&42u32.to_be_bytes());
+    bytes[20..24].copy_from_slice(&2u32.to_be_bytes());
+    let tlv = info.to_tlv();
+    bytes[32..32 + tlv.len()].copy_from_slice(&tlv);
+    match USRPPacket::from_bytes(&bytes) {
+        USRPPacket::Start(packet) => {
+            assert_eq!(packet.sequence_number, 42);
+            assert_eq!(packet.info, Some(info));
+        }
+        _ => panic!("not a start packet"),
+    }
+}
+
+#[test]
+fn round_trips_audio_and_end_packets() {
+    let audio: Vec<i16> = (0..160).map(|x| x * 100 - 8000).collect();
+    let bytes = AudioPacket {
+        sequence_number: 7,
+        transmit: true,
+        audio: audio.clone(),
+    }
+    .to_bytes();
+    assert_eq!(bytes.len(), 32 + 320);
+    match USRPPacket::from_bytes(&bytes) {
+        USRPPacket::Audio(packet) => {
+            assert_eq!(packet.sequence_number, 7);
+            assert!(packet.transmit);
+            assert_eq!(packet.audio, audio);
+        }
+        _ => panic!("not an audio packet"),
+    }
+
+    let bytes = EndPacket { sequence_number: 8 }.to_bytes();
+    assert_eq!(bytes.len(), 32);
+    let packet = USRPPacket::from_bytes(&bytes);
+    assert!(matches!(packet, USRPPacket::End(_)));
+    assert_eq!(packet.sequence_number(), Some(8));
+    assert_eq!(packet.samples(), 0);
+}
+
+#[test]
+fn keeps_unknown_packets() {
+    assert!(matches!(
+        USRPPacket::from_bytes(b"USRP"),
+        USRPPacket::Unknown(_)
+    ));
+    let mut bytes = vec![0; 32];
+    bytes[20..24].copy_from_slice(&5u32.to_be_bytes());
+    assert!(matches!(
+        USRPPacket::from_bytes(&bytes),
+        USRPPacket::Unknown(_)
+    ));
+}